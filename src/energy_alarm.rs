@@ -0,0 +1,55 @@
+use js_sys::{JsString, Object, Reflect};
+use log::*;
+use screeps::{game, prelude::*};
+use wasm_bindgen::{JsCast, JsValue};
+
+// Key in a room's memory counting the ticks its spawn energy has been too low to spawn anything
+const ENERGY_STALL_MEMORY_KEY: &str = "energyStall";
+// Spawn energy stuck that low for longer than this usually means the harvesting chain broke
+const ENERGY_STALL_TICKS: u32 = 300;
+// Once it has, the warning is repeated this often until the room recovers
+const ENERGY_STALL_WARN_INTERVAL: u32 = 100;
+
+// Count the ticks each owned room's spawn energy has stayed below `min_energy`, warning once
+// it's been too long
+pub fn check_energy_stalls(min_energy: u32) {
+    for room in game::rooms().values() {
+        if !room.controller().is_some_and(|controller| controller.my()) {
+            continue;
+        }
+        let memory = room.memory();
+        if !memory.is_object() {
+            continue;
+        }
+        let memory: &Object = memory.unchecked_ref();
+        let key = JsString::from(ENERGY_STALL_MEMORY_KEY);
+
+        let stalled_for = Reflect::get(memory, &key)
+            .ok()
+            .and_then(|ticks| ticks.as_f64())
+            .map_or(0, |ticks| ticks as u32);
+
+        if room.energy_available() >= min_energy {
+            if stalled_for > 0 {
+                if stalled_for > ENERGY_STALL_TICKS {
+                    info!("{} spawn energy recovered after {} ticks", room.name(), stalled_for);
+                }
+                let _ = Reflect::delete_property(memory, &key);
+            }
+            continue;
+        }
+
+        let stalled_for = stalled_for + 1;
+        if stalled_for > ENERGY_STALL_TICKS && (stalled_for - ENERGY_STALL_TICKS) % ENERGY_STALL_WARN_INTERVAL == 1 {
+            warn!(
+                "{} spawn energy has been below {} for {} ticks ({}/{})",
+                room.name(),
+                min_energy,
+                stalled_for,
+                room.energy_available(),
+                room.energy_capacity_available()
+            );
+        }
+        let _ = Reflect::set(memory, &key, &JsValue::from(stalled_for));
+    }
+}
@@ -0,0 +1,57 @@
+use js_sys::{JsString, Object, Reflect};
+use log::*;
+use screeps::{game, objects::Room};
+use wasm_bindgen::{JsCast, JsValue};
+
+// Key in a room's memory holding its rolling energy income and spending per tick, and the share
+// of ticks a spawn sat waiting for energy
+const LEDGER_MEMORY_KEY: &str = "energyLedger";
+// Roughly how many ticks the rolling averages cover; a creep's lifetime evens out spawning
+const LEDGER_WINDOW_TICKS: f64 = 1500.0;
+// How often, in ticks, each room's net energy rate is logged
+const LEDGER_LOG_INTERVAL: u32 = 500;
+
+// Fold this tick's income and spending, and whether a spawn was left waiting for energy, into the
+// room's rolling averages, logging them every so often
+pub fn record(room: &Room, income: u32, spend: u32, starved: bool) {
+    let memory = room.memory();
+    if !memory.is_object() {
+        return;
+    }
+    let memory: &Object = memory.unchecked_ref();
+    let key = JsString::from(LEDGER_MEMORY_KEY);
+
+    let ledger = Reflect::get(memory, &key)
+        .ok()
+        .filter(|ledger| ledger.is_object())
+        .map(|ledger| ledger.unchecked_into::<Object>())
+        .unwrap_or_default();
+    let income = rolling_average(&ledger, "income", income);
+    let spend = rolling_average(&ledger, "spend", spend);
+    let starved = rolling_average(&ledger, "starved", u32::from(starved));
+    let _ = Reflect::set(memory, &key, &ledger);
+
+    if game::time().is_multiple_of(LEDGER_LOG_INTERVAL) {
+        info!(
+            "{} energy per tick: income {:.1}, spend {:.1}, net {:+.1}; spawn waiting on energy {:.0}% of ticks",
+            room.name(),
+            income,
+            spend,
+            income - spend,
+            starved * 100.0
+        );
+    }
+}
+
+// Move the average stored under `field` a window's share of the way towards `value`; a room
+// without one yet starts from `value`
+fn rolling_average(ledger: &Object, field: &str, value: u32) -> f64 {
+    let field = JsString::from(field);
+    let value = value as f64;
+    let average = match Reflect::get(ledger, &field).ok().and_then(|average| average.as_f64()) {
+        Some(average) => average + (value - average) / LEDGER_WINDOW_TICKS,
+        None => value,
+    };
+    let _ = Reflect::set(ledger, &field, &JsValue::from(average));
+    average
+}
@@ -0,0 +1,45 @@
+use std::{any::Any, collections::HashMap};
+
+use screeps::{
+    constants::find::{Find, FindConstant},
+    local::RoomName,
+    objects::{ConstructionSite, Room},
+};
+
+// Results of `Room::find` calls made during the current tick, so that many creeps in the
+// same room share one query instead of each making their own. Build a fresh one every
+// tick; nothing here is valid past the tick it was found in.
+#[derive(Default)]
+pub struct FindCache {
+    results: HashMap<(RoomName, Find), Box<dyn Any>>,
+    build_sites: HashMap<RoomName, Option<ConstructionSite>>,
+}
+
+impl FindCache {
+    pub fn find<T>(&mut self, room: &Room, ty: T) -> Vec<T::Item>
+    where
+        T: FindConstant,
+        T::Item: Clone + 'static,
+    {
+        self.results
+            .entry((room.name(), ty.find_code()))
+            .or_insert_with(|| Box::new(room.find(ty, None)))
+            .downcast_ref::<Vec<T::Item>>()
+            .expect("find constant should always produce the same item type")
+            .clone()
+    }
+
+    // The site all builders in the room work on, picked once for the tick by the first one to ask
+    pub fn build_site(
+        &mut self,
+        room: &Room,
+        pick: impl FnOnce(&mut Self) -> Option<ConstructionSite>,
+    ) -> Option<ConstructionSite> {
+        if let Some(site) = self.build_sites.get(&room.name()) {
+            return site.clone();
+        }
+        let site = pick(self);
+        self.build_sites.insert(room.name(), site.clone());
+        site
+    }
+}
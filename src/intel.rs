@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use screeps::{
+    constants::{ResourceType, StructureType},
+    enums::StructureObject,
+    find, game,
+    game::map::RoomStatus,
+    local::{ObjectId, RoomName},
+    objects::{Deposit, Room, Source, StructureController},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::find_cache::FindCache;
+
+// Deposits are only worth the trip while their cooldown is at most this many ticks
+pub const DEPOSIT_MAX_COOLDOWN: u32 = 50;
+// Deposits are only worth the trip from rooms at most this far away
+const DEPOSIT_RANGE: u32 = 2;
+// Intel older than this many ticks is worth a scout going back to refresh
+const STALE_INTEL_TICKS: u32 = 1000;
+// Power banks are only worth cracking in rooms at most this far away
+const POWER_BANK_RANGE: u32 = 2;
+// ...and only with at least this many ticks left before they decay
+const POWER_BANK_MIN_TICKS: u32 = 2000;
+
+// What a scout saw the last time it was in a room
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomIntel {
+    // username of the controller's owner, if it has one
+    pub owner: Option<String>,
+    // username of whoever has the controller reserved, if anyone
+    #[serde(default)]
+    pub reserved_by: Option<String>,
+    // lets creeps head for the controller before the room is visible again
+    #[serde(default)]
+    pub controller: Option<ObjectId<StructureController>>,
+    pub sources: usize,
+    // lets remote harvesters head for a source before its room is visible again
+    #[serde(default)]
+    pub source_ids: Vec<ObjectId<Source>>,
+    // each deposit seen in the room, with its cooldown after the last harvest
+    #[serde(default)]
+    pub deposits: Vec<(ObjectId<Deposit>, u32)>,
+    // the tick the room's power bank decays on, if it has one
+    #[serde(default)]
+    pub power_bank_decay: Option<u32>,
+    #[serde(default)]
+    pub mineral: Option<ResourceType>,
+    #[serde(default)]
+    pub hostile_structures: usize,
+    // owned by someone else or defended by towers
+    pub hostile: bool,
+    // a scout was killed heading here, or invaders turned up; nobody goes back until this tick
+    #[serde(default)]
+    pub dangerous_until: u32,
+    // couldn't be reached at all; scouts won't try it again
+    pub blocked: bool,
+    pub scouted_at: u32,
+}
+
+pub type RoomIntelMap = HashMap<RoomName, RoomIntel>;
+
+impl RoomIntel {
+    pub fn observe(room: &Room, find_cache: &mut FindCache) -> Self {
+        let controller = room.controller();
+        let hostile_structures = find_cache.find(room, find::HOSTILE_STRUCTURES);
+        let hostile_towers = hostile_structures
+            .iter()
+            .any(|structure| structure.structure_type() == StructureType::Tower);
+        RoomIntel {
+            owner: controller.as_ref().and_then(|controller| controller.owner()).map(|owner| owner.username()),
+            reserved_by: controller
+                .as_ref()
+                .and_then(|controller| controller.reservation())
+                .map(|reservation| reservation.username()),
+            controller: controller.as_ref().map(|controller| controller.id()),
+            sources: find_cache.find(room, find::SOURCES).len(),
+            source_ids: find_cache.find(room, find::SOURCES).iter().map(|source| source.id()).collect(),
+            deposits: find_cache
+                .find(room, find::DEPOSITS)
+                .iter()
+                .map(|deposit| (deposit.id(), deposit.last_cooldown()))
+                .collect(),
+            power_bank_decay: find_cache.find(room, find::STRUCTURES).iter().find_map(|structure| match structure {
+                StructureObject::StructurePowerBank(bank) => Some(game::time() + bank.ticks_to_decay()),
+                _ => None,
+            }),
+            mineral: find_cache.find(room, find::MINERALS).first().map(|mineral| mineral.mineral_type()),
+            hostile_structures: hostile_structures.len(),
+            hostile: hostile_towers || controller.is_some_and(|controller| controller.owner().is_some() && !controller.my()),
+            dangerous_until: 0,
+            blocked: false,
+            scouted_at: game::time(),
+        }
+    }
+
+    pub fn blocked() -> Self {
+        RoomIntel {
+            owner: None,
+            reserved_by: None,
+            controller: None,
+            sources: 0,
+            source_ids: Vec::new(),
+            deposits: Vec::new(),
+            power_bank_decay: None,
+            mineral: None,
+            hostile_structures: 0,
+            hostile: false,
+            dangerous_until: 0,
+            blocked: true,
+            scouted_at: game::time(),
+        }
+    }
+
+    // whether a scout should bother going back to refresh this
+    fn revisitable(&self) -> bool {
+        !self.blocked && !self.hostile && game::time() >= self.dangerous_until && self.stale()
+    }
+
+    fn stale(&self) -> bool {
+        game::time().saturating_sub(self.scouted_at) >= STALE_INTEL_TICKS
+    }
+}
+
+// Whether any room next to this one has never been scouted, or is due another look
+pub fn needs_scouting(from: RoomName, intel: &RoomIntelMap) -> bool {
+    game::map::describe_exits(from)
+        .values()
+        .any(|room_name| intel.get(&room_name).is_none_or(RoomIntel::revisitable))
+}
+
+// Keep creeps away from a room for a while after it turned out to be dangerous, rather than
+// sending the next one straight back in
+pub fn mark_dangerous(room_name: RoomName, intel: &mut RoomIntelMap, ticks: u32) {
+    let until = game::time() + ticks;
+    intel
+        .entry(room_name)
+        .and_modify(|room_intel| room_intel.dangerous_until = until)
+        .or_insert_with(|| RoomIntel {
+            blocked: false,
+            dangerous_until: until,
+            // never actually seen, so as stale as can be once it's safe again
+            scouted_at: 0,
+            ..RoomIntel::blocked()
+        });
+}
+
+pub fn is_dangerous(room_name: RoomName, intel: &RoomIntelMap) -> bool {
+    intel
+        .get(&room_name)
+        .is_some_and(|room_intel| game::time() < room_intel.dangerous_until)
+}
+
+// A neighbouring room that hasn't been scouted yet, or failing that the one that was scouted
+// longest ago, as long as that's long enough ago to be worth a look and it's safe to go back to
+pub fn next_scout_room(from: RoomName, intel: &mut RoomIntelMap) -> Option<RoomName> {
+    let exits: Vec<RoomName> = game::map::describe_exits(from).values().collect();
+
+    // closed rooms can't be entered, so there's no point walking to them
+    for &room_name in &exits {
+        let closed = game::map::get_room_status(room_name).is_some_and(|status| status.status() == RoomStatus::Closed);
+        if closed && !intel.contains_key(&room_name) {
+            intel.insert(room_name, RoomIntel::blocked());
+        }
+    }
+
+    exits.iter().copied().find(|room_name| !intel.contains_key(room_name)).or_else(|| {
+        exits
+            .iter()
+            .copied()
+            .filter_map(|room_name| Some((room_name, intel.get(&room_name)?)))
+            .filter(|(_, room_intel)| room_intel.revisitable())
+            .min_by_key(|(_, room_intel)| room_intel.scouted_at)
+            .map(|(room_name, _)| room_name)
+    })
+}
+
+// The nearest room in reach of `from` where a scout saw a deposit that's still quick to harvest
+pub fn deposit_room(from: RoomName, intel: &RoomIntelMap) -> Option<RoomName> {
+    intel
+        .iter()
+        .filter(|(_, room_intel)| {
+            !room_intel.hostile
+                && room_intel
+                    .deposits
+                    .iter()
+                    .any(|&(_, last_cooldown)| last_cooldown <= DEPOSIT_MAX_COOLDOWN)
+        })
+        .map(|(&room_name, _)| (room_name, game::map::get_room_linear_distance(from, room_name, false)))
+        .filter(|&(_, distance)| distance <= DEPOSIT_RANGE)
+        .min_by_key(|&(_, distance)| distance)
+        .map(|(room_name, _)| room_name)
+}
+
+// The nearest room in reach of `from` where a scout saw a power bank with time left to crack it
+pub fn power_bank_room(from: RoomName, intel: &RoomIntelMap) -> Option<RoomName> {
+    let now = game::time();
+    intel
+        .iter()
+        .filter(|(_, room_intel)| {
+            !room_intel.hostile && room_intel.power_bank_decay.is_some_and(|decay| decay >= now + POWER_BANK_MIN_TICKS)
+        })
+        .map(|(&room_name, _)| (room_name, game::map::get_room_linear_distance(from, room_name, false)))
+        .filter(|&(_, distance)| distance <= POWER_BANK_RANGE)
+        .min_by_key(|&(_, distance)| distance)
+        .map(|(room_name, _)| room_name)
+}
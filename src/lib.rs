@@ -1,46 +1,462 @@
 use std::{
     cell::RefCell,
-    collections::{hash_map::Entry, HashMap, HashSet},
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
 };
 
-use js_sys::{JsString, Object, Reflect};
+use js_sys::{Array, JsString, Object, Reflect};
 use log::*;
 use screeps::{
-    constants::{ErrorCode, Part, ResourceType},
+    constants::{
+        Direction, ErrorCode, Part, ResourceType, StructureType, Terrain, CREEP_LIFE_TIME, LAB_BOOST_ENERGY, LAB_BOOST_MINERAL,
+        CARRY_CAPACITY, CONTROLLER_MAX_UPGRADE_PER_TICK, ENERGY_REGEN_TIME, HARVEST_POWER, MAX_CREEP_SIZE, ROOM_SIZE,
+        SAFE_MODE_COST, SOURCE_ENERGY_CAPACITY, UPGRADE_CONTROLLER_POWER,
+    },
     enums::StructureObject,
     find, game,
-    local::ObjectId,
-    objects::{Creep, Source, StructureController, StructureSpawn, ConstructionSite},
+    local::{ObjectId, Position, RawObjectId, RoomCoordinate, RoomName},
+    pathfinder::{self, SearchOptions},
+    objects::{
+        Creep, Deposit, Mineral, Resource, Room, Ruin, Source, Store, Structure, StructureContainer, StructureController,
+        StructureExtension, StructureLab, StructureLink, StructureNuker, StructurePowerBank, StructurePowerSpawn, StructureSpawn, StructureStorage, StructureTerminal, StructureTower, ConstructionSite, Tombstone,
+    },
     prelude::*,
     HasId, // Add this import at the top of the file
     MaybeHasId, // Add MaybeHasId to the import
 };
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
+use find_cache::FindCache;
+use intel::{RoomIntel, RoomIntelMap};
+
+mod energy_alarm;
+mod energy_ledger;
+mod find_cache;
+mod intel;
+mod link;
 mod logging;
+mod movement;
+mod persistence;
+mod planner;
+mod power_spawn;
+mod tower;
 
 // Define CreepRole enum
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum CreepRole {
     Builder,
     Worker,
+    Miner,
+    // Bound for life to one source and the tile it mines it from, and replaced shortly before it
+    // dies of old age
+    StaticMiner(ObjectId<Source>, Position),
+    Hauler,
+    Repairer,
+    // Does nothing but upgrade the controller, refilling from whatever's closest to it
+    Upgrader,
+    Defender,
+    // Fights from range, keeping out of reach of melee attackers
+    RangedDefender,
+    // Keeps defenders and anyone else hurt in a fight topped up
+    Healer,
+    Scout,
+    MineralMiner,
+    // Harvests deposits in nearby highway rooms and brings the haul home
+    DepositMiner,
+    // The pair sent to crack power banks once the power bank flag is placed: one hits the bank,
+    // the other heals the damage it reflects
+    PowerAttacker,
+    PowerHealer,
+    // Claims or reserves the controllers of flagged rooms
+    Claimer,
+    // Harvests a source in a reserved room next door and carries the energy home
+    RemoteHarvester,
+    // Bound for life to a remote source with a container, ferrying what's harvested into it home
+    RemoteHauler(ObjectId<Source>, RoomName),
 }
 
 // Update CreepTarget enum
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 enum CreepTarget {
     Upgrade(ObjectId<StructureController>),
     Harvest(ObjectId<Source>),
+    // Harvesting a source in another room, which can't be resolved until that room's in sight
+    RemoteHarvest(ObjectId<Source>, RoomName),
+    // Emptying the container by a source in another room, or picking up what's lying around it
+    CollectRemote(ObjectId<Source>, RoomName),
+    // Parked next to a dry source until it regenerates
+    WaitForSource(ObjectId<Source>),
     Build(ObjectId<ConstructionSite>),
     FillSpawn(ObjectId<StructureSpawn>),
+    FillExtension(ObjectId<StructureExtension>),
+    FillTower(ObjectId<StructureTower>),
+    // The container by the controller, which upgraders refill from
+    FillControllerContainer(ObjectId<StructureContainer>),
+    Repair(ObjectId<Structure>),
+    // A wall or rampart being built up towards the hits the controller level calls for
+    Fortify(ObjectId<Structure>),
+    // Miners park on the given tile next to their source and harvest forever
+    Mine(ObjectId<Source>, Position),
+    // Drop miners park on their source's container and harvest into it forever, placing and
+    // building the container first if there isn't one yet
+    DropMine(ObjectId<Source>),
+    Pickup(ObjectId<Resource>),
+    WithdrawContainer(ObjectId<StructureContainer>),
+    WithdrawStorage(ObjectId<StructureStorage>),
+    // Only ever the hub link next to storage; the others are there to send energy, not be emptied
+    WithdrawLink(ObjectId<StructureLink>),
+    LootTombstone(ObjectId<Tombstone>),
+    LootRuin(ObjectId<Ruin>),
+    DepositStorage(ObjectId<StructureStorage>),
+    // Leaving energy on the ground at the collection point, when there's nowhere else for it
+    DropEnergy(Position),
+    Attack(ObjectId<Creep>),
+    RangedAttack(ObjectId<Creep>),
+    Heal(ObjectId<Creep>),
+    Dismantle(ObjectId<Structure>),
+    // Scouts walk into the room and note down what's there
+    Explore(RoomName),
+    // Just getting into the room, for when there's nothing to resolve in it until it's in sight
+    MoveToRoom(RoomName),
+    // The room is kept alongside the controller so the creep can walk there while it's out of sight
+    Claim(ObjectId<StructureController>, RoomName),
+    // Kept up for as long as the creep lives, since reservations tick down constantly
+    Reserve(ObjectId<StructureController>, RoomName),
+    // Wearing down someone else's hold on a controller we want to claim or reserve
+    AttackController(ObjectId<StructureController>),
+    Sign(ObjectId<StructureController>),
+    // Whatever the creep was doing before it went to be renewed, picked back up afterwards
+    Renew(ObjectId<StructureSpawn>, Option<Box<CreepTarget>>),
+    // Taken apart by the spawn once it's next to it
+    Recycle(ObjectId<StructureSpawn>),
+    // Waiting next to a lab for it to boost us, straight after spawning and before any real work
+    GetBoosted(ObjectId<StructureLab>),
+    HarvestMineral(ObjectId<Mineral>),
+    HarvestDeposit(ObjectId<Deposit>),
+    AttackPowerBank(ObjectId<StructurePowerBank>),
+    // Waiting by a nearly cracked power bank in the given room to carry off its power
+    CollectPower(ObjectId<StructurePowerBank>, RoomName),
+    // Storage or terminal, with the mineral being carried
+    DepositMineral(ObjectId<Structure>, ResourceType),
+    // Storage or terminal, for something other than energy
+    WithdrawResource(ObjectId<Structure>, ResourceType),
+    // A hostile structure in the room flagged for looting, with what's being taken out of it
+    Plunder(ObjectId<Structure>, ResourceType),
+    FillLab(ObjectId<StructureLab>, ResourceType),
+    FillNuker(ObjectId<StructureNuker>, ResourceType),
+    FillPowerSpawn(ObjectId<StructurePowerSpawn>, ResourceType),
+    // Moving this much of the resource over from storage, for selling
+    FillTerminal(ObjectId<StructureTerminal>, ResourceType, u32),
+    // Fetching a safe mode's worth of ghodium from storage or the terminal and handing it in
+    GenerateSafeMode(ObjectId<StructureController>),
+    // Towing a creep too slow to walk on its own to the given tile
+    Pull(ObjectId<Creep>, Position),
+    // Sent to the named flag from the console, holding there until the flag's removed
+    MoveToFlag(String),
+    // Holding a load of energy by the spawn once everything's full, so the next refill doesn't
+    // have to wait on a trip to fetch it
+    HoldEnergy(ObjectId<StructureSpawn>),
+    // Nothing to do; wait out of the way near the tile until the given tick, then look again
+    Idle { pos: Position, until: u32 },
+}
+
+impl CreepTarget {
+    // The object this target reserves, for targets where a second creep going after the same
+    // one would be wasted effort
+    fn claimed_id(&self) -> Option<RawObjectId> {
+        match self {
+            CreepTarget::Harvest(id) => Some((*id).into()),
+            CreepTarget::RemoteHarvest(id, _) => Some((*id).into()),
+            CreepTarget::WaitForSource(id) => Some((*id).into()),
+            CreepTarget::Build(id) => Some((*id).into()),
+            CreepTarget::FillSpawn(id) => Some((*id).into()),
+            CreepTarget::FillExtension(id) => Some((*id).into()),
+            CreepTarget::FillTower(id) => Some((*id).into()),
+            CreepTarget::FillControllerContainer(id) => Some((*id).into()),
+            CreepTarget::WithdrawLink(id) => Some((*id).into()),
+            CreepTarget::Reserve(id, _) => Some((*id).into()),
+            CreepTarget::AttackController(id) => Some((*id).into()),
+            CreepTarget::Sign(id) => Some((*id).into()),
+            CreepTarget::Pull(id, _) => Some((*id).into()),
+            CreepTarget::Plunder(id, _) => Some((*id).into()),
+            CreepTarget::GenerateSafeMode(id) => Some((*id).into()),
+            CreepTarget::CollectPower(id, _) => Some((*id).into()),
+            _ => None,
+        }
+    }
+
+    // Body parts a creep needs at least one working of to act on this target
+    fn required_parts(&self) -> &'static [Part] {
+        match self {
+            // miners drop what they harvest, so they can do without CARRY
+            CreepTarget::Mine(..) | CreepTarget::DropMine(_) | CreepTarget::Dismantle(_) => &[Part::Work],
+            CreepTarget::Upgrade(_)
+            | CreepTarget::Harvest(_)
+            | CreepTarget::RemoteHarvest(..)
+            | CreepTarget::WaitForSource(_)
+            | CreepTarget::Build(_)
+            | CreepTarget::Repair(_)
+            | CreepTarget::Fortify(_)
+            | CreepTarget::HarvestMineral(_)
+            | CreepTarget::HarvestDeposit(_) => {
+                &[Part::Work, Part::Carry]
+            }
+            CreepTarget::FillSpawn(_)
+            | CreepTarget::FillExtension(_)
+            | CreepTarget::FillTower(_)
+            | CreepTarget::FillControllerContainer(_)
+            | CreepTarget::Pickup(_)
+            | CreepTarget::CollectRemote(..)
+            | CreepTarget::WithdrawContainer(_)
+            | CreepTarget::WithdrawStorage(_)
+            | CreepTarget::WithdrawLink(_)
+            | CreepTarget::LootTombstone(_)
+            | CreepTarget::LootRuin(_)
+            | CreepTarget::DepositStorage(_)
+            | CreepTarget::DropEnergy(_)
+            | CreepTarget::DepositMineral(..)
+            | CreepTarget::WithdrawResource(..)
+            | CreepTarget::Plunder(..)
+            | CreepTarget::FillLab(..)
+            | CreepTarget::FillNuker(..)
+            | CreepTarget::FillPowerSpawn(..)
+            | CreepTarget::FillTerminal(..)
+            | CreepTarget::GenerateSafeMode(_)
+            | CreepTarget::CollectPower(..)
+            | CreepTarget::HoldEnergy(_) => &[Part::Carry],
+            CreepTarget::Attack(_) | CreepTarget::AttackPowerBank(_) => &[Part::Attack],
+            CreepTarget::RangedAttack(_) => &[Part::RangedAttack],
+            CreepTarget::Heal(_) => &[Part::Heal],
+            CreepTarget::Explore(_) | CreepTarget::MoveToRoom(_) | CreepTarget::MoveToFlag(_) | CreepTarget::Pull(..) => {
+                &[Part::Move]
+            }
+            CreepTarget::Claim(..) | CreepTarget::Reserve(..) | CreepTarget::AttackController(_) => &[Part::Claim],
+            // any creep that can walk up to the controller can sign it
+            CreepTarget::Sign(_)
+            | CreepTarget::Renew(..)
+            | CreepTarget::Recycle(_)
+            | CreepTarget::GetBoosted(_)
+            | CreepTarget::Idle { .. } => &[],
+        }
+    }
 }
 
+// Miners never leave their tile, so they only need a single MOVE part, and a source is
+// fully drained by 5 WORK parts so they never need to be any bigger
+const MINER_BODY: [Part; 6] = [Part::Work, Part::Work, Part::Work, Part::Work, Part::Work, Part::Move];
+// Miners with this many WORK parts drain a source on their own, so they get its container to
+// themselves
+const DROP_MINE_WORK_PARTS: u8 = 5;
+// A static miner this close to dying of old age no longer counts for its source, so its
+// replacement is spawned and walking over before it's gone
+const STATIC_MINER_REPLACE_TICKS: u32 = 100;
+// Keepers stay within this range of their lair, and attack anyone harvesting a source this close
+const KEEPER_GUARD_RANGE: u32 = 5;
+// A lair this close to spawning its keeper already counts as guarding its source
+const KEEPER_SPAWN_WARNING_TICKS: u32 = 50;
+const SOURCE_KEEPER_USERNAME: &str = "Source Keeper";
+// A single CLAIM part is all claiming takes
+const CLAIMER_BODY: [Part; 2] = [Part::Claim, Part::Move];
+
+// Creeps per role each room keeps alive; miner and hauler counts follow the sources
+const WORKER_QUOTA: usize = 2;
+const BUILDER_QUOTA: usize = 2;
+const REPAIRER_QUOTA: usize = 1;
+// Every room keeps one upgrader so the controller never downgrades, and one more for each this
+// much energy banked over the storage floor, up to the cap
+const UPGRADER_STORAGE_STEP: u32 = 50_000;
+const UPGRADER_MAX: usize = 4;
+// Only wanted while there are hostiles in the room
+const DEFENDER_QUOTA: usize = 2;
+// One healer for every this many defenders
+const DEFENDERS_PER_HEALER: usize = 2;
+// Only wanted while a room next door hasn't been scouted
+const SCOUT_QUOTA: usize = 1;
+// Scouts stay out of a room this long after one was killed on the way there
+const SCOUT_DEATH_AVOID_TICKS: u32 = 5000;
+// Remote harvesters stay out of a room this long after fleeing invaders there
+const REMOTE_UNSAFE_TICKS: u32 = 1500;
+// Once the hostiles are gone, defenders hold the edge they came from for this long in case they
+// come back, then get recycled
+const DEFENDER_GRACE_TICKS: u32 = 200;
+// How far in from the room edge defenders hold it, clear of the exit tiles
+const DEFENDER_EDGE_INSET: u8 = 3;
+// Haulers per room that hold on to a load by the spawn once it and the extensions are full
+const PRELOAD_HAULERS: usize = 2;
+// ...waiting within this range of it
+const PRELOAD_RANGE: u32 = 2;
+// Remote haulers pick up energy dropped within this range of their source when its container's empty
+const REMOTE_PICKUP_RANGE: u32 = 3;
+// Reach of a ranged attack, and the distance ranged creeps keep from melee hostiles
+const RANGED_ATTACK_RANGE: u32 = 3;
+// Ranged creeps re-pick the most threatening hostile this often
+const RANGED_RETARGET_INTERVAL: u32 = 5;
+// Reach of a ranged heal; anything closer than this but not adjacent gets one
+const RANGED_HEAL_RANGE: u32 = 3;
+// Placing a flag with this name in a room sends its creeps to tear down hostile structures there
+const DISMANTLE_FLAG: &str = "dismantle";
+// Placing a flag with this name in a hostile room sends empty haulers to carry off what its
+// structures hold
+const LOOT_FLAG: &str = "loot";
+// Placing a flag with this name anywhere lets rooms send crews out to crack nearby power banks
+const POWER_BANK_FLAG: &str = "powerbank";
+// A hauler heads out to a power bank once it's down to this many hits
+const POWER_BANK_HAUL_HITS: u32 = 300_000;
+// Haulers wait this far from the bank for it to pop
+const POWER_BANK_WAIT_RANGE: u32 = 3;
+// Power bank attackers back off below this fraction of their hits until the healer catches up
+const POWER_ATTACKER_RETREAT_FRACTION: f32 = 0.5;
+// Creeps with CLAIM parts take the controller of the room holding a flag with this name
+const CLAIM_FLAG: &str = "claim";
+// Rooms holding a flag whose name starts with this get their controller reserved, one creep each
+const RESERVE_FLAG_PREFIX: &str = "reserve";
+// Creeps with nothing to do wait by a flag with this name in their room
+const IDLE_FLAG: &str = "idle";
+// Without one, they wait this many steps from the controller towards the middle of the room
+const IDLE_CONTROLLER_OFFSET: u32 = 4;
+// Anywhere within this range of the idle spot will do, so idle creeps don't queue up for one tile
+const IDLE_RANGE: u32 = 2;
+// How long idle creeps wait before looking for something to do again
+const IDLE_TICKS: u32 = 10;
+// Written on the controllers of our rooms
+const CONTROLLER_SIGN: &str = "Territory of the swarm";
+
+// Creeps go back to be renewed once they have fewer ticks than this left to live, plus the
+// per-part allowance below; bigger bodies take more renewals to top back up
+const RENEW_TTL_THRESHOLD: u32 = 100;
+const RENEW_TTL_PER_PART: u32 = 10;
+// and stay at the spawn until they're back above this
+const RENEW_TTL_TARGET: u32 = 1200;
+// Creeps cheaper than this aren't worth the trip, it's better to spawn a new one
+const RENEW_MIN_BODY_COST: u32 = 500;
+// Creeps whose body costs less than this fraction of what the room now spawns for their role
+// are recycled to make room for a proper one
+const RECYCLE_COST_FRACTION: f32 = 0.5;
+// Spawns hold off on cheaper creeps while the room has at least this fraction of the energy
+// the most wanted creep costs
+const SPAWN_WAIT_FRACTION: f32 = 0.8;
+// How often, in ticks, rooms get checked for construction sites to place
+const PLANNER_INTERVAL: u32 = 50;
+// Past this fraction of the CPU limit, the passes that can wait a tick get skipped
+const CPU_HIGH_WATER: f64 = 0.9;
+// How often, in ticks, rooms get roads planned between their spawn, sources and controller
+const ROAD_PLANNER_INTERVAL: u32 = 500;
+// Reach of upgrading, repairing and building
+const WORK_RANGE: u32 = 3;
+// Names tried per spawn attempt before giving up until next tick
+const SPAWN_NAME_ATTEMPTS: u32 = 3;
+// Key in a room's memory holding an object of lab id -> the reagent it should be kept stocked with
+const LAB_INPUTS_MEMORY_KEY: &str = "labs";
+// Set to true in a creep's memory to have it recycled whatever shape it's in
+const RECYCLE_MEMORY_KEY: &str = "recycle";
+// Set to a flag's name in a creep's memory to send it there, whatever it was doing
+const FLAG_MEMORY_KEY: &str = "flag";
+// Labs get topped up once they hold less than this much of their reagent
+const LAB_REAGENT_MIN: u32 = 1000;
+// Creeps waiting to be boosted give up and get to work once they're this many ticks old
+const BOOST_TIMEOUT_TICKS: u32 = 50;
+// The nuker only gets energy while storage holds more than this, so loading it can't starve the room
+const NUKER_STORAGE_SURPLUS: u32 = 100_000;
+// Processing power burns through energy, so the power spawn is only fed energy while storage
+// holds more than this
+const POWER_SPAWN_STORAGE_SURPLUS: u32 = 150_000;
+const MAX_CONTROLLER_LEVEL: u8 = 8;
+// A max level controller only gets upgraded to keep it from downgrading, while it has fewer
+// ticks to go than this
+const MAX_LEVEL_DOWNGRADE_FLOOR: u32 = 150_000;
+// A controller this close to downgrading has every creep that can upgrade drop what it's doing
+// for it, until it's back over the safe level
+const DOWNGRADE_DANGER_TICKS: u32 = 3000;
+const DOWNGRADE_SAFE_TICKS: u32 = 6000;
+// Rooms with fewer safe modes than this in hand turn stored ghodium into another one
+const SAFE_MODE_RESERVE: u32 = 2;
+// Power is only fetched for the power spawn once it's down to less than this
+const POWER_SPAWN_POWER_MIN: u32 = 20;
+
+// Storage keeps this much of each resource; anything over it is moved to the terminal to be sold
+const TERMINAL_ENERGY_THRESHOLD: u32 = 200_000;
+const TERMINAL_MINERAL_THRESHOLD: u32 = 10_000;
+// Energy stops going to the terminal once it holds this much, so it keeps room for minerals
+const TERMINAL_ENERGY_CAP: u32 = 50_000;
+// Structures are picked for repair once they drop below this fraction of their repair goal
+const REPAIR_THRESHOLD: f32 = 0.8;
+// Roads and containers decay on their own; repairers patrol for ones under this fraction of their hits
+const INFRASTRUCTURE_REPAIR_THRESHOLD: f32 = 0.5;
+// A repairer is only wanted once this many hits have decayed off the room's roads and containers
+const REPAIRER_DECAYED_HITS: u32 = 20_000;
+// Walls and ramparts have millions of hits; only ever repair them up to this many
+const BARRIER_HITS_CAP: u32 = 10_000;
+// Hits walls and ramparts get built up to, by controller level
+const FORTIFY_HITS: [u32; 9] = [0, 0, 10_000, 50_000, 100_000, 300_000, 1_000_000, 3_000_000, 10_000_000];
+// A fortifying creep moves on once another barrier has this many fewer hits than the one it's on
+const FORTIFY_SWITCH_MARGIN: u32 = 5_000;
+// Ramparts start on a single hit and decay fast; below this they're taken on straight away
+const FRESH_RAMPART_HITS: u32 = 1_000;
+// While under attack, repairers shore up the weakest rampart within this range of a spawn
+const DEFENSE_RAMPART_RANGE: u32 = 5;
+// Creeps carrying energy only wait at a dry source if it regenerates within this many ticks;
+// otherwise they go and spend what they have
+const SOURCE_WAIT_MAX_TICKS: u32 = 50;
+// Full haulers with nowhere to deliver drop their energy this many steps from the spawn, towards
+// the controller, for the other creeps to pick up
+const COLLECTION_POINT_OFFSET: u32 = 2;
+// and don't pick anything back up from within this range of it
+const COLLECTION_POINT_RANGE: u32 = 1;
+// Empty creeps detour to dropped energy piles at least this big before going to a source
+const PICKUP_MIN_AMOUNT: u32 = 50;
+// Energy left in storage as an emergency reserve that only workers, who refill the spawns, withdraw
+const STORAGE_ENERGY_FLOOR: u32 = 10_000;
+// Surplus energy stops going into storage past this, and goes to the controller instead
+const STORAGE_DEPOSIT_CAP: u32 = 500_000;
+// Towers get topped up once they drop below this fraction of their energy capacity
+const TOWER_REFILL_THRESHOLD: f32 = 0.7;
+// A container within this range of the controller is there for the upgraders
+const CONTROLLER_CONTAINER_RANGE: u32 = 3;
+// and gets topped up by haulers once it drops below this fraction of its capacity
+const CONTROLLER_CONTAINER_REFILL_THRESHOLD: f32 = 0.75;
+// How often, in ticks, rooms without one are checked for a new controller container
+const CONTROLLER_CONTAINER_RECHECK: u32 = 100;
+// Upgraders drop what they're doing to fill spawns and extensions while the room's spawn
+// energy is below this fraction of its capacity
+const UPGRADE_THROTTLE_THRESHOLD: f32 = 0.5;
+
+type CreepInfoMap = HashMap<String, (CreepRole, Option<CreepTarget>)>;
+// How many creeps are harvesting or mining at each source
+type SourceCrowds = HashMap<ObjectId<Source>, usize>;
+// Each room's controller container, if it has one, and the tick it was last looked for
+type ControllerContainerMap = HashMap<RoomName, (Option<ObjectId<StructureContainer>>, u32)>;
+
 // Update thread_local storage to include role
 thread_local! {
-    static CREEP_INFO: RefCell<HashMap<String, (CreepRole, Option<CreepTarget>)>> = RefCell::new(HashMap::new());
+    static CREEP_INFO: RefCell<CreepInfoMap> = RefCell::new(HashMap::new());
+    // creeps already warned about missing body parts, so it's only logged once each
+    static BODY_WARNED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    // creeps already warned about attacking a controller too soon, for the same reason
+    static TIRED_WARNED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    // body units already warned about being trimmed to the part limit, since bodies are built
+    // every tick
+    static TRIM_WARNED: RefCell<HashSet<Vec<Part>>> = RefCell::new(HashSet::new());
+    static ROOM_INTEL: RefCell<RoomIntelMap> = RefCell::new(HashMap::new());
+    static CONTROLLER_CONTAINERS: RefCell<ControllerContainerMap> = RefCell::new(HashMap::new());
+    // walkable tiles around each source; terrain never changes, so these are only counted once
+    static SOURCE_TILES: RefCell<HashMap<ObjectId<Source>, usize>> = RefCell::new(HashMap::new());
+    // creep footsteps per tile, halved at every memory cleanup so old routes fade out
+    static TRAFFIC: RefCell<HashMap<Position, u32>> = RefCell::new(HashMap::new());
+    // where and when defenders last saw a hostile in each room
+    static LAST_THREAT: RefCell<HashMap<RoomName, (Position, u32)>> = RefCell::new(HashMap::new());
+    // the room each scout was in last tick and its ticks to live then, to tell a scout that was
+    // killed from one that died of old age
+    static SCOUT_LAST_SEEN: RefCell<HashMap<String, (RoomName, u32)>> = RefCell::new(HashMap::new());
+    // rooms whose controllers are being rescued from downgrading
+    static DOWNGRADE_EMERGENCIES: RefCell<HashSet<RoomName>> = RefCell::new(HashSet::new());
+    // steps from our nearest storage to each remote source; neither end moves, so these are only
+    // worked out once
+    static REMOTE_PATHS: RefCell<HashMap<ObjectId<Source>, u32>> = RefCell::new(HashMap::new());
 }
 
 static INIT_LOGGING: std::sync::Once = std::sync::Once::new();
+static LOAD_CREEP_INFO: std::sync::Once = std::sync::Once::new();
 
 // add wasm_bindgen to any function you would like to expose for call from js
 // to use a reserved name as a function name, use `js_name`:
@@ -50,44 +466,187 @@ pub fn game_loop() {
         // show all output of Info level, adjust as needed
         logging::setup_logging(logging::Info);
     });
+    // the console can turn this up for a while without a redeploy
+    logging::apply_memory_level(logging::Info);
 
     debug!("loop starting! CPU: {}", game::cpu::get_used());
 
+    persistence::request_segments();
+    LOAD_CREEP_INFO.call_once(|| {
+        // first tick after a global reset; pick up where the last VM left off
+        if let Some(saved) = persistence::load_creep_info() {
+            info!("restored info for {} creeps", saved.len());
+            CREEP_INFO.with(|creep_info_refcell| *creep_info_refcell.borrow_mut() = saved);
+        }
+        if let Some(saved) = persistence::load_room_intel() {
+            info!("restored intel for {} rooms", saved.len());
+            ROOM_INTEL.with(|room_intel_refcell| *room_intel_refcell.borrow_mut() = saved);
+        }
+    });
+
+    // room.find results shared by everything that runs this tick
+    let mut find_cache = FindCache::default();
+
     CREEP_INFO.with(|creep_info_refcell| {
         let mut creep_info = creep_info_refcell.borrow_mut();
+        // everything already being worked on; creeps picking a new target this tick
+        // add theirs as they go
+        let mut claimed: HashSet<RawObjectId> = game::creeps()
+            .keys()
+            .filter_map(|creep_name| creep_info.get(&creep_name)?.1.as_ref()?.claimed_id())
+            .collect();
+        mark_scout_deaths(&mut creep_info);
+        // creeps picking a source this tick count themselves in as they go, same as claims
+        let mut source_crowds = source_crowds(&creep_info);
         debug!("running creeps");
         for creep in game::creeps().values() {
-            run_creep(&creep, &mut creep_info);
+            record_traffic(&creep);
+            run_creep(&creep, &mut creep_info, &mut find_cache, &mut claimed, &mut source_crowds);
         }
     });
+    debug!("creeps done! CPU: {}", game::cpu::get_used());
+
+    debug!("running towers");
+    tower::run_towers();
+
+    debug!("running links");
+    link::run_links();
+
+    debug!("running power spawns");
+    power_spawn::run_power_spawns();
+
+    debug!("running boost labs");
+    CREEP_INFO.with(|creep_info_refcell| boost_waiting_creeps(&creep_info_refcell.borrow()));
 
     debug!("running spawns");
-    let mut additional = 0;
+    let mut additional: u32 = 0;
+    // energy each room's spawns have already committed this tick and the roles it went on; neither
+    // shows up in the room or in game::creeps() until next tick, so a second spawn in the room
+    // would otherwise spend the same energy on the same role
+    let mut committed: HashMap<RoomName, (u32, Vec<CreepRole>)> = HashMap::new();
+    // rooms with a spawn sitting idle this tick for want of energy for what's queued
+    let mut starved: HashSet<RoomName> = HashSet::new();
     for spawn in game::spawns().values() {
         debug!("running spawn {}", spawn.name());
 
-        let body = [Part::Move, Part::Move, Part::Carry, Part::Work];
-        if spawn.room().unwrap().energy_available() >= body.iter().map(|p| p.cost()).sum() {
+        let room = spawn.room().unwrap();
+        CREEP_INFO.with(|creep_info_refcell| {
+            recycle_waiting_creep(&spawn, &room, &mut creep_info_refcell.borrow_mut(), &mut find_cache)
+        });
+
+        // size bodies for a full room, unless there's nobody left to fill the extensions
+        let bootstrap = find_cache.find(&room, find::MY_CREEPS).is_empty();
+        let capacity = if bootstrap {
+            room.energy_available()
+        } else {
+            room.energy_capacity_available()
+        };
+        let (spent, pending) = committed.entry(room.name()).or_default();
+        // empty when every role is at its quota
+        let queue = CREEP_INFO.with(|creep_info_refcell| {
+            spawn_queue(&room, &creep_info_refcell.borrow(), &mut find_cache, capacity, pending)
+        });
+        // economy creeps are sized, and held back, so there's always energy left over for a defender
+        let queue: Vec<(CreepRole, Vec<Part>, u32)> = queue
+            .into_iter()
+            .map(|(role, body)| {
+                let reserve = defense_reserve(&role, capacity, bootstrap);
+                let body = if reserve > 0 { build_body(capacity - reserve, &role) } else { body };
+                let needed = body_cost(&body) + reserve;
+                (role, body, needed)
+            })
+            .collect();
+
+        // the most important creep the room can afford, unless the top of the queue is nearly
+        // affordable; then hold the spawn for it rather than tying it up with something cheaper
+        let energy = room.energy_available().saturating_sub(*spent);
+        let wanted = !queue.is_empty();
+        let next = match queue.first() {
+            Some(&(_, _, needed)) if energy < needed && energy as f32 >= needed as f32 * SPAWN_WAIT_FRACTION => None,
+            _ => queue.into_iter().find(|&(_, _, needed)| needed <= energy),
+        };
+        if wanted && next.is_none() && spawn.spawning().is_none() {
+            starved.insert(room.name());
+        }
+
+        if let Some((role, body, _)) = next.filter(|_| spawn.spawning().is_none()) {
             let name_base = game::time();
-            let name = format!("{}-{}", name_base, additional);
-            let role = if additional % 2 == 0 { CreepRole::Builder } else { CreepRole::Worker };
-            
-            match spawn.spawn_creep(&body, &name) {
-                Ok(()) => {
-                    CREEP_INFO.with(|creep_info_refcell| {
-                        let mut creep_info = creep_info_refcell.borrow_mut();
-                        creep_info.insert(name.clone(), (role, None));
-                    });
-                    additional += 1;
-                },
-                Err(e) => warn!("couldn't spawn: {:?}", e),
+            let mut spawned = None;
+            // a name clash, say with a creep spawned just before a reset, only needs a new suffix
+            for _ in 0..SPAWN_NAME_ATTEMPTS {
+                let name = format!("{}-{}", name_base, additional);
+                additional += 1;
+
+                match spawn.spawn_creep(&body, &name) {
+                    Ok(()) => {
+                        spawned = Some(name);
+                        break;
+                    },
+                    Err(ErrorCode::NameExists) => warn!("creep name {} is taken, trying another", name),
+                    Err(e) => {
+                        warn!("couldn't spawn: {:?}", e);
+                        break;
+                    },
+                }
+            }
+
+            if let Some(name) = spawned {
+                info!(
+                    "spawning {:?} {} in {}: {} parts costing {}",
+                    role,
+                    name,
+                    room.name(),
+                    body.len(),
+                    body_cost(&body)
+                );
+                *spent += body_cost(&body);
+                pending.push(role.clone());
+                // boosts are only waited on if a lab has them ready now
+                let unboosted: Vec<(Part, Option<ResourceType>)> = body.iter().map(|&part| (part, None)).collect();
+                let target = boost_lab(&room, &role, &unboosted, &mut find_cache).map(|lab| CreepTarget::GetBoosted(lab.id()));
+                CREEP_INFO.with(|creep_info_refcell| {
+                    let mut creep_info = creep_info_refcell.borrow_mut();
+                    creep_info.insert(name, (role, target));
+                });
+                continue;
             }
         }
+
+        // nothing new is being spawned, so the spawn is free to renew whoever's waiting on it
+        CREEP_INFO.with(|creep_info_refcell| {
+            renew_waiting_creep(&spawn, &room, &creep_info_refcell.borrow(), &mut find_cache)
+        });
+    }
+
+    // keep a running tally of each room's energy income against its spending
+    CREEP_INFO.with(|creep_info_refcell| {
+        let mut flows = energy_flows(&creep_info_refcell.borrow());
+        for (room_name, (spent, _)) in &committed {
+            flows.entry(*room_name).or_default().1 += spent;
+        }
+        for room in game::rooms().values().filter(|room| room.controller().is_some_and(|controller| controller.my())) {
+            let (income, spend) = flows.get(&room.name()).copied().unwrap_or_default();
+            energy_ledger::record(&room, income, spend, starved.contains(&room.name()));
+        }
+    });
+
+    // creeps, towers and spawns have had their turn; everything after this can wait a tick
+    let over_budget = cpu_over_budget();
+
+    // placing sites is expensive and rarely has anything new to do
+    if !over_budget && game::time().is_multiple_of(PLANNER_INTERVAL) {
+        debug!("running planner");
+        planner::place_extensions();
+    }
+    // pathing every route is expensive, and the roads only need laying once
+    if !over_budget && game::time().is_multiple_of(ROAD_PLANNER_INTERVAL) {
+        debug!("planning roads");
+        planner::place_roads();
     }
 
     // memory cleanup; memory gets created for all creeps upon spawning, and any time move_to
-    // is used; this should be removed if you're using RawMemory/serde for persistence
-    if game::time() % 1000 == 0 {
+    // is used; our own creep info is persisted separately and needs the same pruning
+    if game::time().is_multiple_of(1000) {
         info!("running memory cleanup");
         let mut alive_creeps = HashSet::new();
         // add all living creep names to a hashset
@@ -95,6 +654,29 @@ pub fn game_loop() {
             alive_creeps.insert(creep_name);
         }
 
+        BODY_WARNED.with(|body_warned_refcell| {
+            body_warned_refcell.borrow_mut().retain(|creep_name| alive_creeps.contains(creep_name));
+        });
+        TIRED_WARNED.with(|tired_warned_refcell| {
+            tired_warned_refcell.borrow_mut().retain(|creep_name| alive_creeps.contains(creep_name));
+        });
+        TRAFFIC.with(|traffic_refcell| {
+            traffic_refcell.borrow_mut().retain(|_, steps| {
+                *steps /= 2;
+                *steps > 0
+            });
+        });
+
+        CREEP_INFO.with(|creep_info_refcell| {
+            creep_info_refcell.borrow_mut().retain(|creep_name, _| {
+                let alive = alive_creeps.contains(creep_name);
+                if !alive {
+                    info!("deleting info for dead creep {}", creep_name);
+                }
+                alive
+            });
+        });
+
         // grab `Memory.creeps` (if it exists)
         if let Ok(memory_creeps) = Reflect::get(&screeps::memory::ROOT, &JsString::from("creeps")) {
             // convert from JsValue to Object
@@ -113,129 +695,3740 @@ pub fn game_loop() {
         }
     }
 
+    // warn when a room can't even afford the smallest worker for too long
+    energy_alarm::check_energy_stalls(body_cost(&build_body(0, &CreepRole::Worker)));
+
+    CREEP_INFO.with(|creep_info_refcell| persistence::save_creep_info(&creep_info_refcell.borrow()));
+    ROOM_INTEL.with(|room_intel_refcell| persistence::save_room_intel(&room_intel_refcell.borrow()));
+
+    // skip building the summary entirely when nobody's going to see it
+    if !over_budget && log_enabled!(Level::Info) {
+        CREEP_INFO.with(|creep_info_refcell| info!("{}", census_summary(&creep_info_refcell.borrow())));
+    }
+
     info!("sheep done! cpu: {}", game::cpu::get_used())
 }
 
-fn run_creep(creep: &Creep, creep_info: &mut HashMap<String, (CreepRole, Option<CreepTarget>)>) {
+// Energy harvested and spent in each room this tick, going by what every creep is working on
+// and in range of: each WORK part harvests 2 a tick, and spends 1 on upgrading or repairs
+fn energy_flows(creep_info: &CreepInfoMap) -> HashMap<RoomName, (u32, u32)> {
+    let mut flows: HashMap<RoomName, (u32, u32)> = HashMap::new();
+    for creep in game::creeps().values() {
+        let Some((_, Some(target))) = creep_info.get(&creep.name()) else {
+            continue;
+        };
+        let work = creep.get_active_bodyparts(Part::Work) as u32;
+        let creep_pos = creep.pos();
+        let (income, spend) = match target {
+            CreepTarget::Harvest(source_id)
+            | CreepTarget::Mine(source_id, _)
+            | CreepTarget::DropMine(source_id)
+            | CreepTarget::RemoteHarvest(source_id, _) => {
+                let at_source = source_id.resolve().is_some_and(|source| creep_pos.is_near_to(source.pos()));
+                (if at_source { work * HARVEST_POWER } else { 0 }, 0)
+            }
+            CreepTarget::Upgrade(controller_id) => {
+                let at_controller = controller_id.resolve().is_some_and(|controller| creep_pos.in_range_to(controller.pos(), WORK_RANGE));
+                (0, if at_controller { work * UPGRADE_CONTROLLER_POWER } else { 0 })
+            }
+            CreepTarget::Repair(structure_id) | CreepTarget::Fortify(structure_id) => {
+                let at_structure = structure_id.resolve().is_some_and(|structure| creep_pos.in_range_to(structure.pos(), WORK_RANGE));
+                (0, if at_structure { work } else { 0 })
+            }
+            _ => continue,
+        };
+        let flow = flows.entry(creep_pos.room_name()).or_default();
+        flow.0 += income;
+        flow.1 += spend;
+    }
+    flows
+}
+
+// Scouts that were killed since last tick in the room they were heading for; that room gets
+// avoided for a while. Scouts that died of old age, or anywhere on the way, say nothing about it.
+// Either way their info is dropped so it's only counted once
+fn mark_scout_deaths(creep_info: &mut CreepInfoMap) {
+    let creeps = game::creeps();
+    let dead: Vec<(String, Option<RoomName>)> = creep_info
+        .iter()
+        .filter(|(creep_name, _)| creeps.get(creep_name.to_string()).is_none())
+        .filter_map(|(creep_name, (role, target))| match (role, target) {
+            (CreepRole::Scout, Some(CreepTarget::Explore(room_name))) => Some((creep_name.clone(), Some(*room_name))),
+            (CreepRole::Scout, _) => Some((creep_name.clone(), None)),
+            _ => None,
+        })
+        .collect();
+    SCOUT_LAST_SEEN.with(|last_seen_refcell| {
+        let mut last_seen = last_seen_refcell.borrow_mut();
+        for (creep_name, room_name) in dead {
+            let seen = last_seen.remove(&creep_name);
+            if let (Some(room_name), Some((seen_in, ticks_to_live))) = (room_name, seen) {
+                if seen_in == room_name && ticks_to_live > 1 {
+                    warn!("scout {} was killed in {}, avoiding it for a while", creep_name, room_name);
+                    ROOM_INTEL.with(|room_intel_refcell| {
+                        intel::mark_dangerous(room_name, &mut room_intel_refcell.borrow_mut(), SCOUT_DEATH_AVOID_TICKS)
+                    });
+                }
+            }
+            creep_info.remove(&creep_name);
+        }
+
+        // where the living ones are, and how long they had left, for next tick
+        for creep in creeps.values() {
+            let is_scout = creep_info.get(&creep.name()).is_some_and(|(role, _)| *role == CreepRole::Scout);
+            if let (true, Some(ticks_to_live)) = (is_scout, creep.ticks_to_live()) {
+                last_seen.insert(creep.name(), (creep.pos().room_name(), ticks_to_live));
+            }
+        }
+    });
+}
+
+// Whether this tick has used enough of the CPU limit that anything optional should be skipped
+fn cpu_over_budget() -> bool {
+    let used = game::cpu::get_used();
+    let limit = game::cpu::limit() as f64;
+    let over = used >= limit * CPU_HIGH_WATER;
+    if over {
+        warn!("cpu at {:.1} of {}, skipping planning and the census this tick", used, limit);
+    }
+    over
+}
+
+fn run_creep(
+    creep: &Creep,
+    creep_info: &mut CreepInfoMap,
+    find_cache: &mut FindCache,
+    claimed: &mut HashSet<RawObjectId>,
+    source_crowds: &mut SourceCrowds,
+) {
     if creep.spawning() {
         return;
     }
     let name = creep.name();
     debug!("running creep {}", name);
+    // the room can fail to resolve on an exit tile; skip the creep rather than the whole tick,
+    // it'll get another go next tick
+    let Some(room) = creep.room() else {
+        warn!("couldn't resolve room for creep {}, skipping it this tick", name);
+        return;
+    };
+
+    // miners spread out over the sources by where everyone else is mining, which can't be looked
+    // at once our own entry is borrowed
+    let (taken_spots, drop_mined) = match creep_info.get(&name) {
+        Some((CreepRole::Miner, target)) if !matches!(target, Some(CreepTarget::Mine(..) | CreepTarget::DropMine(_))) => {
+            (taken_mining_spots(creep_info), drop_mined_sources(creep_info))
+        }
+        _ => (Vec::new(), Vec::new()),
+    };
+    let stranded = match creep_info.get(&name) {
+        Some((CreepRole::Hauler, target)) if !matches!(target, Some(CreepTarget::Pull(..))) => stranded_miners(creep_info),
+        _ => Vec::new(),
+    };
+    let holding = match creep_info.get(&name) {
+        Some((CreepRole::Hauler, target)) if !matches!(target, Some(CreepTarget::HoldEnergy(_))) => {
+            energy_holders(creep_info, room.name())
+        }
+        _ => 0,
+    };
 
     let (role, target) = creep_info.entry(name.clone())
         .or_insert_with(|| (CreepRole::Worker, None));
 
     // Function to make the creep say its role
     let say_role = |creep: &Creep, role: &CreepRole| {
-        let role_name = match role {
-            CreepRole::Builder => "Builder",
-            CreepRole::Worker => "Worker",
-        };
-        creep.say(role_name, false);
+        let _ = creep.say(role_label(role), false);
     };
 
+    // a creep that's lost the parts for its target since last tick has to find another
+    if target.as_ref().is_some_and(|target| !has_parts_for(creep, target)) {
+        *target = None;
+    }
+
+    // big creeps about to die of old age go back to be renewed rather than being replaced
+    if !matches!(target, Some(CreepTarget::Renew(..) | CreepTarget::Recycle(_))) {
+        // only while the spawn has idle time and energy to spare for it
+        let idle_spawn = find_cache
+            .find(&room, find::MY_SPAWNS)
+            .into_iter()
+            .filter(|spawn| spawn.spawning().is_none())
+            .min_by_key(|spawn| creep.pos().get_range_to(spawn.pos()));
+        if let Some(spawn) = idle_spawn.filter(|_| worth_renewing(creep, role, &room) && !spawn_energy_low(&room)) {
+            *target = Some(CreepTarget::Renew(spawn.id(), target.take().map(Box::new)));
+        } else if body_obsolete(creep, role, &room) || marked_for_recycling(creep) {
+            // outgrown by the room, or retired by hand; hand the energy back to put towards a
+            // bigger replacement
+            let spawn = find_cache
+                .find(&room, find::MY_SPAWNS)
+                .into_iter()
+                .min_by_key(|spawn| creep.pos().get_range_to(spawn.pos()));
+            if let Some(spawn) = spawn {
+                info!("recycling {:?} {}", role, creep.name());
+                *target = Some(CreepTarget::Recycle(spawn.id()));
+            }
+        }
+    }
+
+    let target_before = target.clone();
+
+    // a new rampart decays away within a few ticks unless someone gets to it quickly
+    if *role == CreepRole::Repairer
+        && creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+        && !matches!(target, Some(CreepTarget::Fortify(_) | CreepTarget::Renew(..) | CreepTarget::Recycle(_)))
+    {
+        if let Some(rampart) = weakest_barrier(&room, find_cache).filter(|barrier| {
+            barrier.structure_type() == StructureType::Rampart && barrier.as_structure().hits() < FRESH_RAMPART_HITS
+        }) {
+            *target = Some(CreepTarget::Fortify(rampart.as_structure().id()));
+        }
+    }
+
+    // defenders holding an edge or on their way to be recycled turn back the moment hostiles show up
+    if matches!(role, CreepRole::Defender | CreepRole::RangedDefender)
+        && matches!(target, Some(CreepTarget::Idle { .. } | CreepTarget::Recycle(_)))
+        && under_attack(&room, find_cache)
+    {
+        *target = None;
+    }
+
+    // losing a controller level is far worse than anything else going undone
+    if matches!(role, CreepRole::Worker | CreepRole::Builder | CreepRole::Repairer | CreepRole::Upgrader)
+        && creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+        && !matches!(target, Some(CreepTarget::Upgrade(_) | CreepTarget::Recycle(_)))
+        && downgrade_emergency(&room)
+    {
+        if let Some(controller) = room.controller() {
+            *target = Some(CreepTarget::Upgrade(controller.id()));
+        }
+    }
+
+    // a flag assigned from the console overrides everything else; taking it off hands the creep
+    // back to the automation
+    match (flag_assignment(creep), &*target) {
+        (Some(flag_name), Some(CreepTarget::MoveToFlag(current))) if flag_name == *current => {}
+        (Some(flag_name), _) => *target = Some(CreepTarget::MoveToFlag(flag_name)),
+        (None, Some(CreepTarget::MoveToFlag(_))) => *target = None,
+        (None, _) => {}
+    }
+
+    // set once the creep has looked for something new to do this tick
+    let mut searched = false;
+
     match target {
-        Some(CreepTarget::Upgrade(controller_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+        Some(CreepTarget::Renew(spawn_id, previous)) => {
             say_role(creep, role);
-            if let Some(controller) = controller_id.resolve() {
-                creep
-                    .upgrade_controller(&controller)
-                    .unwrap_or_else(|e| match e {
-                        ErrorCode::NotInRange => {
-                            let _ = creep.move_to(&controller);
+            let ticks_to_live = creep.ticks_to_live().unwrap_or(0);
+            match spawn_id.resolve() {
+                Some(spawn) if spawn.spawning().is_none() && ticks_to_live < RENEW_TTL_TARGET => {
+                    // the spawn renews us from its side of the loop once we're next to it
+                    if !creep.pos().is_near_to(spawn.pos()) {
+                        let _ = movement::move_to(creep, &spawn);
+                    }
+                }
+                // renewed, or the spawn got busy; back to what it was doing
+                _ => *target = previous.take().map(|previous| *previous),
+            }
+        }
+        Some(CreepTarget::Recycle(spawn_id)) => {
+            say_role(creep, role);
+            if let Some(spawn) = spawn_id.resolve() {
+                // the spawn does the recycling from its side of the loop
+                if !creep.pos().is_near_to(spawn.pos()) {
+                    let _ = movement::move_to(creep, &spawn);
+                }
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::Mine(source_id, mining_pos)) => {
+            say_role(creep, role);
+            if creep.pos() != *mining_pos {
+                // walking to the mining tile, or shoved off of it, unless a hauler is pulling us there
+                let pulled = creep.try_id().is_some_and(|id| claimed.contains(&id.into()));
+                if !pulled {
+                    let _ = movement::move_to(creep, *mining_pos);
+                }
+            } else if let Some(source) = source_id.resolve() {
+                // a full miner keeps harvesting; the excess lands in the container
+                // beneath it or on the ground
+                creep.harvest(&source).unwrap_or_else(|e| match e {
+                    // source is depleted, wait for it to regenerate
+                    ErrorCode::NotEnough => {}
+                    _ => {
+                        warn!("couldn't mine: {:?}", e);
+                        *target = None;
+                    }
+                });
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::DropMine(source_id)) => {
+            say_role(creep, role);
+            // deliberately no guard on a full store; drop miners never go anywhere else
+            if let Some(source) = source_id.resolve() {
+                match drop_mining_spot(&source) {
+                    Some(spot) if creep.pos() != spot => {
+                        let _ = movement::move_to(creep, spot);
+                    }
+                    Some(spot) => {
+                        let site = spot
+                            .find_in_range(find::MY_CONSTRUCTION_SITES, 0)
+                            .into_iter()
+                            .next();
+                        let store = creep.store();
+                        match site {
+                            // build up the container beneath us whenever we've harvested a full load
+                            Some(site) if store.get_used_capacity(Some(ResourceType::Energy)) > 0
+                                && store.get_free_capacity(Some(ResourceType::Energy)) == 0 =>
+                            {
+                                if let Err(e) = creep.build(&site) {
+                                    warn!("couldn't build mining container: {:?}", e);
+                                }
+                            }
+                            _ => creep.harvest(&source).unwrap_or_else(|e| match e {
+                                // source is depleted, wait for it to regenerate
+                                ErrorCode::NotEnough => {}
+                                _ => {
+                                    warn!("couldn't drop mine: {:?}", e);
+                                    *target = None;
+                                }
+                            }),
                         }
-                        _ => {
-                            warn!("couldn't upgrade: {:?}", e);
-                            *target = None;
+                    }
+                    None => {
+                        // no container or site yet, put one down on the best tile next to the source
+                        match mining_position(&room, &source, find_cache, &[]) {
+                            Some(pos) => {
+                                if let Err(e) = pos.create_construction_site(StructureType::Container, None) {
+                                    warn!("couldn't place mining container in {}: {:?}", room.name(), e);
+                                    *target = None;
+                                }
+                            }
+                            None => *target = None,
                         }
-                    });
+                    }
+                }
             } else {
                 *target = None;
             }
         }
-        Some(CreepTarget::Harvest(source_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+        Some(CreepTarget::Pull(pulled_id, destination)) => {
             say_role(creep, role);
-            if let Some(source) = source_id.resolve() {
-                if creep.pos().is_near_to(source.pos()) {
-                    creep.harvest(&source).unwrap_or_else(|e| {
-                        warn!("couldn't harvest: {:?}", e);
+            match pulled_id.resolve() {
+                // once it's there it's on its own
+                Some(pulled) if pulled.pos() != *destination => {
+                    if !creep.pos().is_near_to(pulled.pos()) {
+                        let _ = movement::move_to(creep, &pulled);
+                    } else if let Err(e) = creep.pull(&pulled).and_then(|_| pulled.move_pulled_by(creep)) {
+                        warn!("couldn't pull: {:?}", e);
                         *target = None;
+                    } else if creep.pos() == *destination {
+                        // step back onto the pulled creep's tile, swapping places with it
+                        if let Some(direction) = creep.pos().get_direction_to(pulled.pos()) {
+                            let _ = creep.move_direction(direction);
+                        }
+                    } else {
+                        let _ = movement::move_to(creep, *destination);
+                    }
+                }
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::Pickup(resource_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            // the pile may have decayed or been picked up by someone else since last tick
+            if let Some(resource) = resource_id.resolve() {
+                handle_action(creep, resource.pos(), creep.pickup(&resource), target, "pick up");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::WithdrawContainer(container_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(container) = container_id.resolve() {
+                // if someone else emptied it, this clears the target to go find another
+                handle_action(creep, container.pos(), creep.withdraw(&container, ResourceType::Energy, None), target, "withdraw");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::WithdrawStorage(storage_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(storage) = storage_id.resolve() {
+                handle_action(creep, storage.pos(), creep.withdraw(&storage, ResourceType::Energy, None), target, "withdraw from storage");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::WithdrawLink(link_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(link) = link_id.resolve() {
+                handle_action(creep, link.pos(), creep.withdraw(&link, ResourceType::Energy, None), target, "withdraw from link");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::LootTombstone(tombstone_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            let any_resource = loots_any_resource(creep, role);
+            match tombstone_id.resolve().and_then(|tombstone| Some((loot_resource(&tombstone.store(), any_resource)?, tombstone))) {
+                Some((resource, tombstone)) => {
+                    handle_action(creep, tombstone.pos(), creep.withdraw(&tombstone, resource, None), target, "loot tombstone");
+                }
+                // decayed or emptied by another creep
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::LootRuin(ruin_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            let any_resource = loots_any_resource(creep, role);
+            match ruin_id.resolve().and_then(|ruin| Some((loot_resource(&ruin.store(), any_resource)?, ruin))) {
+                Some((resource, ruin)) => {
+                    handle_action(creep, ruin.pos(), creep.withdraw(&ruin, resource, None), target, "loot ruin");
+                }
+                // decayed or emptied by another creep
+                _ => *target = None,
+            }
+        }
+        // attacking doesn't use energy, so there's no store check here
+        Some(CreepTarget::Attack(hostile_id)) => {
+            say_role(creep, role);
+            match hostile_id.resolve() {
+                Some(hostile) if hostile.pos().room_name() == creep.pos().room_name() => {
+                    LAST_THREAT.with(|last_threat_refcell| {
+                        last_threat_refcell.borrow_mut().insert(hostile.pos().room_name(), (hostile.pos(), game::time()))
                     });
+                    // defenders close in from a rampart whenever there's a free one next to the hostile
+                    let rampart = if *role == CreepRole::Defender && !creep.pos().is_near_to(hostile.pos()) {
+                        free_rampart_near(&room, find_cache, hostile.pos())
+                    } else {
+                        None
+                    };
+                    match rampart {
+                        Some(rampart_pos) => {
+                            let _ = movement::move_to(creep, rampart_pos);
+                        }
+                        None => handle_action(creep, hostile.pos(), creep.attack(&hostile), target, "attack"),
+                    }
+                }
+                // dead, or left the room
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::RangedAttack(hostile_id)) => {
+            say_role(creep, role);
+            let hostile = if game::time().is_multiple_of(RANGED_RETARGET_INTERVAL) {
+                // don't keep chasing a scout while a healer undoes all the damage
+                ranged_attack_target(creep, &room, find_cache)
+            } else {
+                hostile_id.resolve().filter(|hostile| hostile.pos().room_name() == creep.pos().room_name())
+            };
+
+            if let Some(hostile) = hostile {
+                if let Some(id) = hostile.try_id() {
+                    *hostile_id = id;
+                }
+                LAST_THREAT.with(|last_threat_refcell| {
+                    last_threat_refcell.borrow_mut().insert(hostile.pos().room_name(), (hostile.pos(), game::time()))
+                });
+                let range = creep.pos().get_range_to(hostile.pos());
+                let adjacent = find_cache
+                    .find(&room, find::HOSTILE_CREEPS)
+                    .iter()
+                    .filter(|other| creep.pos().is_near_to(other.pos()))
+                    .count();
+
+                let result = if adjacent > 1 {
+                    creep.ranged_mass_attack()
+                } else if range <= RANGED_ATTACK_RANGE {
+                    creep.ranged_attack(&hostile)
                 } else {
-                    let _ = creep.move_to(&source);
+                    Ok(())
+                };
+                if let Err(e) = result {
+                    warn!("couldn't ranged attack: {:?}", e);
+                    *target = None;
+                }
+
+                if range > RANGED_ATTACK_RANGE {
+                    let _ = movement::move_to(creep, &hostile);
+                } else if range < RANGED_ATTACK_RANGE && hostile.get_active_bodyparts(Part::Attack) > 0 {
+                    // back off out of melee reach, without getting cornered
+                    if let Some(step) = kite_step(creep, &room, find_cache) {
+                        let _ = creep.move_direction(step);
+                    }
                 }
             } else {
+                // dead, or left the room
                 *target = None;
             }
         }
-        Some(CreepTarget::Build(site_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+        Some(CreepTarget::Heal(patient_id)) => {
             say_role(creep, role);
-            if let Some(site) = site_id.resolve() {
-                creep.build(&site).unwrap_or_else(|e| match e {
-                    ErrorCode::NotInRange => {
-                        let _ = creep.move_to(&site);
-                    }
-                    _ => {
-                        warn!("couldn't build: {:?}", e);
+            match patient_id.resolve() {
+                Some(patient)
+                    if patient.hits() < patient.hits_max() && patient.pos().room_name() == creep.pos().room_name() =>
+                {
+                    let range = creep.pos().get_range_to(patient.pos());
+                    let result = if range <= 1 {
+                        creep.heal(&patient)
+                    } else if range <= RANGED_HEAL_RANGE {
+                        creep.ranged_heal(&patient)
+                    } else {
+                        Ok(())
+                    };
+                    if let Err(e) = result {
+                        warn!("couldn't heal: {:?}", e);
                         *target = None;
                     }
+
+                    // a full strength heal needs to be adjacent
+                    if range > 1 {
+                        let _ = movement::move_to(creep, &patient);
+                    }
+                }
+                // healed up, dead, or left the room
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::Explore(room_name)) => {
+            say_role(creep, role);
+            let room_name = *room_name;
+            if creep.pos().room_name() == room_name {
+                let intel = RoomIntel::observe(&room, find_cache);
+                info!("scouted {}: {:?}", room_name, intel);
+                // head straight on to the next room rather than hanging around on the exit tile
+                *target = ROOM_INTEL.with(|room_intel_refcell| {
+                    let mut room_intel = room_intel_refcell.borrow_mut();
+                    room_intel.insert(room_name, intel);
+                    intel::next_scout_room(room_name, &mut room_intel).map(CreepTarget::Explore)
                 });
-            } else {
+            } else if let Err(ErrorCode::NoPath) = movement::move_to(creep, room_center(room_name)) {
+                warn!("no path to {}, marking it blocked", room_name);
+                *target = ROOM_INTEL.with(|room_intel_refcell| {
+                    let mut room_intel = room_intel_refcell.borrow_mut();
+                    room_intel.insert(room_name, RoomIntel::blocked());
+                    intel::next_scout_room(creep.pos().room_name(), &mut room_intel).map(CreepTarget::Explore)
+                });
+            }
+        }
+        Some(CreepTarget::MoveToRoom(room_name)) => {
+            say_role(creep, role);
+            if creep.pos().room_name() == *room_name {
+                // in sight now, so whatever brought us here can be picked properly
+                *target = None;
+            } else if let Err(ErrorCode::NoPath) = movement::move_to(creep, room_center(*room_name)) {
+                warn!("no path to {}", room_name);
                 *target = None;
             }
         }
-        Some(CreepTarget::FillSpawn(spawn_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+        Some(CreepTarget::Claim(controller_id, room_name)) => {
             say_role(creep, role);
-            if let Some(spawn) = spawn_id.resolve() {
-                creep.transfer(&spawn, ResourceType::Energy, None).unwrap_or_else(|e| match e {
-                    ErrorCode::NotInRange => {
-                        let _ = creep.move_to(&spawn);
-                    }
-                    _ => {
-                        warn!("couldn't transfer energy: {:?}", e);
+            if creep.pos().room_name() != *room_name {
+                // the controller can't be resolved until the room is in sight
+                let _ = movement::move_to(creep, room_center(*room_name));
+            } else {
+                match controller_id.resolve() {
+                    Some(controller) if controller.my() => {
+                        info!("claimed {}", room_name);
+                        if let Some(flag) = game::flags().get(CLAIM_FLAG.to_string()) {
+                            flag.remove();
+                        }
                         *target = None;
                     }
-                });
+                    // someone else's has to be worn down before it can be ours
+                    Some(controller) if held_by_others(creep, &controller) => {
+                        *target = Some(CreepTarget::AttackController(controller.id()));
+                    }
+                    Some(controller) => match creep.claim_controller(&controller) {
+                        Ok(()) => {}
+                        Err(ErrorCode::NotInRange) => {
+                            let _ = movement::move_to(creep, &controller);
+                        }
+                        // the flag stays put, so the room gets claimed once the GCL goes up; until
+                        // then claim_target won't hand it out and the claimer idles
+                        Err(ErrorCode::GclNotEnough) => {
+                            warn!("GCL {} is too low to claim {}", game::gcl::level(), room_name);
+                            *target = None;
+                        }
+                        Err(e) => {
+                            warn!("couldn't claim: {:?}", e);
+                            *target = None;
+                        }
+                    },
+                    _ => *target = None,
+                }
+            }
+        }
+        Some(CreepTarget::Reserve(controller_id, room_name)) => {
+            say_role(creep, role);
+            if creep.pos().room_name() != *room_name {
+                let _ = movement::move_to(creep, room_center(*room_name));
+            } else if let Some(controller) = controller_id.resolve() {
+                if held_by_others(creep, &controller) {
+                    // someone else's reservation has to be worn down before ours can start
+                    *target = Some(CreepTarget::AttackController(controller.id()));
+                } else {
+                    handle_action(creep, controller.pos(), creep.reserve_controller(&controller), target, "reserve");
+                }
             } else {
                 *target = None;
             }
         }
-        _ => {
-            // No target or invalid target, find a new one
-            let room = creep.room().expect("couldn't resolve creep room");
-            
-            if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
-                match role {
-                    CreepRole::Builder => {
-                        if let Some(site) = room.find(find::CONSTRUCTION_SITES, None).first() {
-                            if let Some(id) = site.try_id() {
-                                *target = Some(CreepTarget::Build(id));
-                                say_role(creep, role);
-                            } else {
-                                warn!("Construction site has no id");
+        Some(CreepTarget::AttackController(controller_id)) => {
+            say_role(creep, role);
+            match controller_id.resolve() {
+                Some(controller) if held_by_others(creep, &controller) => {
+                    if !creep.pos().is_near_to(controller.pos()) {
+                        let _ = movement::move_to(creep, &controller);
+                    } else if controller.upgrade_blocked().unwrap_or(0) == 0 {
+                        creep.attack_controller(&controller).unwrap_or_else(|e| match e {
+                            ErrorCode::Tired => TIRED_WARNED.with(|tired_warned_refcell| {
+                                if tired_warned_refcell.borrow_mut().insert(creep.name()) {
+                                    warn!("creep {} attacked the controller in {} too soon", creep.name(), controller.pos().room_name());
+                                }
+                            }),
+                            _ => {
+                                warn!("couldn't attack controller: {:?}", e);
+                                *target = None;
                             }
-                        } else if let Some(controller) = room.controller() {
-                            *target = Some(CreepTarget::Upgrade(controller.id()));
-                            say_role(creep, role);
-                        }
+                        });
                     }
-                    CreepRole::Worker => {
-                        if let Some(spawn) = room.find(find::MY_SPAWNS, None).first() {
-                            if spawn.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
-                                *target = Some(CreepTarget::FillSpawn(spawn.id()));
-                                say_role(creep, role);
-                            } else if let Some(controller) = room.controller() {
-                                *target = Some(CreepTarget::Upgrade(controller.id()));
-                                say_role(creep, role);
-                            }
-                        }
+                }
+                // free to take now; claim it if it's flagged for claiming, otherwise reserve it
+                Some(controller) => {
+                    let room_name = controller.pos().room_name();
+                    let flagged = game::flags()
+                        .get(CLAIM_FLAG.to_string())
+                        .is_some_and(|flag| flag.pos().room_name() == room_name);
+                    *target = if flagged && can_claim_room() {
+                        Some(CreepTarget::Claim(controller.id(), room_name))
+                    } else {
+                        Some(CreepTarget::Reserve(controller.id(), room_name))
+                    };
+                }
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::Sign(controller_id)) => {
+            say_role(creep, role);
+            if let Some(controller) = controller_id.resolve() {
+                match creep.sign_controller(&controller, CONTROLLER_SIGN) {
+                    // signed, no need to do it again
+                    Ok(()) => *target = None,
+                    Err(ErrorCode::NotInRange) => {
+                        let _ = movement::move_to(creep, &controller);
+                    }
+                    Err(e) => {
+                        warn!("couldn't sign controller: {:?}", e);
+                        *target = None;
                     }
                 }
-            } else if let Some(source) = room.find(find::SOURCES_ACTIVE, None).first() {
-                *target = Some(CreepTarget::Harvest(source.id()));
-                say_role(creep, role);
+            } else {
+                *target = None;
             }
         }
-    }
-}
\ No newline at end of file
+        Some(CreepTarget::HarvestMineral(mineral_id)) if creep.store().get_free_capacity(None) > 0 => {
+            say_role(creep, role);
+            match mineral_id.resolve() {
+                Some(mineral) if mineral.mineral_amount() > 0 => {
+                    if !creep.pos().is_near_to(mineral.pos()) {
+                        let _ = movement::move_to(creep, &mineral);
+                    } else if extractor_ready(&room, find_cache) {
+                        // no point asking while the extractor is cooling down
+                        creep.harvest(&mineral).unwrap_or_else(|e| {
+                            warn!("couldn't harvest mineral: {:?}", e);
+                            *target = None;
+                        });
+                    }
+                }
+                // depleted until it regenerates
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::AttackPowerBank(bank_id)) => {
+            say_role(creep, role);
+            match bank_id.resolve() {
+                // every hit comes back at half strength, so hold off while we're hurt
+                Some(_) if hits_fraction(creep.hits(), creep.hits_max()) < POWER_ATTACKER_RETREAT_FRACTION => {}
+                Some(bank) => handle_action(creep, bank.pos(), creep.attack(&bank), target, "attack power bank"),
+                // cracked, or decayed
+                None => *target = None,
+            }
+        }
+        Some(CreepTarget::CollectPower(bank_id, room_name)) => {
+            say_role(creep, role);
+            if creep.store().get_free_capacity(None) == 0 {
+                *target = haul_home(creep);
+            } else if creep.pos().room_name() != *room_name {
+                let _ = movement::move_to(creep, room_center(*room_name));
+            } else if let Some(bank) = bank_id.resolve() {
+                // out of the crew's way until it pops
+                if !creep.pos().in_range_to(bank.pos(), POWER_BANK_WAIT_RANGE) {
+                    let _ = movement::move_to(creep, &bank);
+                }
+            } else if let Some(power) = find_cache
+                .find(&room, find::DROPPED_RESOURCES)
+                .into_iter()
+                .find(|resource| resource.resource_type() == ResourceType::Power)
+            {
+                handle_action(creep, power.pos(), creep.pickup(&power), target, "pick up power");
+            } else {
+                // all collected, or it decayed before it popped
+                *target = haul_home(creep);
+            }
+        }
+        Some(CreepTarget::HarvestDeposit(deposit_id)) if creep.store().get_free_capacity(None) > 0 => {
+            say_role(creep, role);
+            match deposit_id.resolve() {
+                Some(deposit) => {
+                    if !creep.pos().is_near_to(deposit.pos()) {
+                        let _ = movement::move_to(creep, &deposit);
+                    } else if deposit.cooldown() == 0 {
+                        // still cooling down otherwise, and asking would only fail
+                        creep.harvest(&deposit).unwrap_or_else(|e| {
+                            warn!("couldn't harvest deposit: {:?}", e);
+                            *target = None;
+                        });
+                    }
+                }
+                // decayed, or we've left its room
+                None => *target = None,
+            }
+        }
+        Some(CreepTarget::DepositMineral(structure_id, resource)) if creep.store().get_used_capacity(Some(*resource)) > 0 => {
+            say_role(creep, role);
+            let resource = *resource;
+            if let Some(structure) = structure_id.resolve().map(StructureObject::from) {
+                if let Some(transferable) = structure.as_transferable() {
+                    handle_action(creep, structure.pos(), creep.transfer(transferable, resource, None), target, "deposit mineral");
+                } else {
+                    *target = None;
+                }
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::WithdrawResource(structure_id, resource)) if creep.store().get_free_capacity(None) > 0 => {
+            say_role(creep, role);
+            let resource = *resource;
+            if let Some(structure) = structure_id.resolve().map(StructureObject::from) {
+                if let Some(withdrawable) = structure.as_withdrawable() {
+                    // clears the target if someone else got the last of it
+                    handle_action(creep, structure.pos(), creep.withdraw(withdrawable, resource, None), target, "withdraw resource");
+                } else {
+                    *target = None;
+                }
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::Plunder(structure_id, resource)) => {
+            say_role(creep, role);
+            let resource = *resource;
+            // it may have been destroyed or changed hands since we set out
+            let structure = structure_id
+                .resolve()
+                .map(StructureObject::from)
+                .filter(|structure| structure.as_owned().is_some_and(|owned| !owned.my()));
+            if creep.store().get_free_capacity(None) == 0 {
+                *target = haul_home(creep);
+            } else if let Some(withdrawable) = structure.as_ref().and_then(|structure| structure.as_withdrawable()) {
+                creep.withdraw(withdrawable, resource, None).unwrap_or_else(|e| match e {
+                    ErrorCode::NotInRange => {
+                        let _ = movement::move_to(creep, withdrawable.pos());
+                    }
+                    // cleaned out, take home whatever we got
+                    ErrorCode::NotEnough => *target = haul_home(creep),
+                    _ => {
+                        warn!("couldn't plunder {:?}: {:?}", resource, e);
+                        *target = haul_home(creep);
+                    }
+                });
+            } else {
+                *target = haul_home(creep);
+            }
+        }
+        Some(CreepTarget::GetBoosted(lab_id)) => {
+            say_role(creep, role);
+            let age = CREEP_LIFE_TIME.saturating_sub(creep.ticks_to_live().unwrap_or(0));
+            let body: Vec<(Part, Option<ResourceType>)> =
+                creep.body().iter().map(|part| (part.part(), part.boost())).collect();
+            // the labs boost us from their side; we just go from lab to lab until there's nothing
+            // left that a lab can give
+            match boost_lab(&room, role, &body, find_cache) {
+                _ if age > BOOST_TIMEOUT_TICKS => {
+                    warn!("creep {} gave up waiting to be boosted", creep.name());
+                    *target = None;
+                }
+                Some(lab) => {
+                    if lab.id() != *lab_id {
+                        *target = Some(CreepTarget::GetBoosted(lab.id()));
+                    }
+                    if !creep.pos().is_near_to(lab.pos()) {
+                        let _ = movement::move_to(creep, &lab);
+                    }
+                }
+                None => *target = None,
+            }
+        }
+        Some(CreepTarget::GenerateSafeMode(controller_id)) => {
+            say_role(creep, role);
+            let carried = creep.store().get_used_capacity(Some(ResourceType::Ghodium));
+            match controller_id.resolve() {
+                Some(controller) if carried >= SAFE_MODE_COST => {
+                    match creep.generate_safe_mode(&controller) {
+                        Ok(()) => {
+                            info!("generated a safe mode in {}", room.name());
+                            *target = None;
+                        }
+                        Err(ErrorCode::NotInRange) => {
+                            let _ = movement::move_to(creep, &controller);
+                        }
+                        Err(e) => {
+                            warn!("couldn't generate safe mode: {:?}", e);
+                            *target = None;
+                        }
+                    }
+                }
+                Some(_) => {
+                    let missing = SAFE_MODE_COST - carried;
+                    match ghodium_source(&room, missing) {
+                        Some(structure) => {
+                            if let Some(withdrawable) = structure.as_withdrawable() {
+                                let result = creep.withdraw(withdrawable, ResourceType::Ghodium, Some(missing));
+                                handle_action(creep, structure.pos(), result, target, "withdraw ghodium");
+                            }
+                        }
+                        // taken for something else in the meantime; anything carried goes back
+                        // like any other leftover reagent
+                        None => *target = None,
+                    }
+                }
+                None => *target = None,
+            }
+        }
+        Some(CreepTarget::FillLab(lab_id, resource)) if creep.store().get_used_capacity(Some(*resource)) > 0 => {
+            say_role(creep, role);
+            let resource = *resource;
+            if let Some(lab) = lab_id.resolve() {
+                creep.transfer(&lab, resource, None).unwrap_or_else(|e| match e {
+                    ErrorCode::NotInRange => {
+                        let _ = movement::move_to(creep, &lab);
+                    }
+                    // topped up already, or holding a different mineral; try another lab
+                    ErrorCode::Full | ErrorCode::InvalidTarget => {
+                        *target = creep
+                            .room()
+                            .and_then(|room| lab_needing(&room, find_cache, resource))
+                            .filter(|other| other.id() != lab.id())
+                            .map(|other| CreepTarget::FillLab(other.id(), resource));
+                    }
+                    _ => {
+                        warn!("couldn't fill lab: {:?}", e);
+                        *target = None;
+                    }
+                });
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillNuker(nuker_id, resource)) if creep.store().get_used_capacity(Some(*resource)) > 0 => {
+            say_role(creep, role);
+            let resource = *resource;
+            if let Some(nuker) = nuker_id.resolve() {
+                handle_action(creep, nuker.pos(), creep.transfer(&nuker, resource, None), target, "fill nuker");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillPowerSpawn(power_spawn_id, resource)) if creep.store().get_used_capacity(Some(*resource)) > 0 => {
+            say_role(creep, role);
+            let resource = *resource;
+            if let Some(power_spawn) = power_spawn_id.resolve() {
+                handle_action(creep, power_spawn.pos(), creep.transfer(&power_spawn, resource, None), target, "fill power spawn");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillTerminal(terminal_id, resource, amount)) => {
+            say_role(creep, role);
+            let (resource, amount) = (*resource, *amount);
+            let carried = creep.store().get_used_capacity(Some(resource));
+            match (terminal_id.resolve(), creep.room().and_then(|room| room.storage())) {
+                // empty handed, so fetch the load from storage first
+                (Some(_), Some(storage)) if carried == 0 => {
+                    handle_action(creep, storage.pos(), creep.withdraw(&storage, resource, Some(amount)), target, "withdraw for terminal");
+                }
+                (Some(terminal), _) if carried > 0 => match creep.transfer(&terminal, resource, Some(carried.min(amount))) {
+                    // delivered, anything left over goes back wherever it belongs
+                    Ok(()) => *target = None,
+                    Err(ErrorCode::NotInRange) => {
+                        let _ = movement::move_to(creep, &terminal);
+                    }
+                    Err(e) => {
+                        warn!("couldn't fill terminal with {:?}: {:?}", resource, e);
+                        *target = None;
+                    }
+                },
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::Upgrade(controller_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            // the controller is the last place energy should go while spawning is starved
+            let refill = creep
+                .room()
+                .filter(|room| spawn_energy_low(room) && !downgrade_emergency(room))
+                .and_then(|room| closest_fill_target(creep, &room, find_cache, claimed));
+            if let Some(fill) = refill {
+                *target = Some(fill);
+            } else if let Some(controller) = controller_id.resolve() {
+                // upgraders keep at it regardless, there's only ever one in a max level room
+                if controller_topped_up(&controller) && *role != CreepRole::Upgrader {
+                    // far enough from downgrading again, the energy can go somewhere useful
+                    *target = None;
+                } else {
+                    handle_action(creep, controller.pos(), creep.upgrade_controller(&controller), target, "upgrade");
+                }
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::Harvest(source_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(source) = source_id.resolve() {
+                if source.energy() == 0 {
+                    *target = worth_waiting_for(creep, &source).then(|| CreepTarget::WaitForSource(source.id()));
+                } else {
+                    handle_action(creep, source.pos(), creep.harvest(&source), target, "harvest");
+                }
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::WaitForSource(source_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            match source_id.resolve() {
+                // back again, pick up harvesting straight away
+                Some(source) if source.energy() > 0 => {
+                    if creep.pos().is_near_to(source.pos()) {
+                        let _ = creep.harvest(&source);
+                    }
+                    *target = Some(CreepTarget::Harvest(source.id()));
+                }
+                Some(source) if worth_waiting_for(creep, &source) => {
+                    if !creep.pos().is_near_to(source.pos()) {
+                        let _ = movement::move_to(creep, &source);
+                    }
+                }
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::Build(site_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(site) = site_id.resolve() {
+                handle_action(creep, site.pos(), creep.build(&site), target, "build");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillSpawn(spawn_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(spawn) = spawn_id.resolve() {
+                handle_action(creep, spawn.pos(), creep.transfer(&spawn, ResourceType::Energy, None), target, "transfer energy");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillExtension(extension_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(extension) = extension_id.resolve() {
+                creep.transfer(&extension, ResourceType::Energy, None).unwrap_or_else(|e| match e {
+                    ErrorCode::NotInRange => {
+                        let _ = movement::move_to(creep, &extension);
+                    }
+                    // another creep got there first; pick the next one now rather than idling a tick
+                    ErrorCode::Full => {
+                        *target = creep.room().and_then(|room| delivery_target(creep, &room, find_cache, claimed, false));
+                    }
+                    _ => {
+                        warn!("couldn't transfer energy: {:?}", e);
+                        *target = None;
+                    }
+                });
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillTower(tower_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(tower) = tower_id.resolve() {
+                handle_action(creep, tower.pos(), creep.transfer(&tower, ResourceType::Energy, None), target, "fill tower");
+            } else {
+                // destroyed
+                *target = None;
+            }
+        }
+        Some(CreepTarget::FillControllerContainer(container_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(container) = container_id.resolve() {
+                handle_action(creep, container.pos(), creep.transfer(&container, ResourceType::Energy, None), target, "fill controller container");
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::RemoteHarvest(_, room_name) | CreepTarget::CollectRemote(_, room_name))
+            if creep.pos().room_name() == *room_name && find_cache.find(&room, find::HOSTILE_CREEPS).iter().any(is_fighter) =>
+        {
+            say_role(creep, role);
+            warn!("invaders in {}, {} is heading home", room_name, creep.name());
+            ROOM_INTEL.with(|room_intel_refcell| {
+                intel::mark_dangerous(*room_name, &mut room_intel_refcell.borrow_mut(), REMOTE_UNSAFE_TICKS)
+            });
+            *target = home_room(creep).map(CreepTarget::MoveToRoom);
+        }
+        Some(CreepTarget::RemoteHarvest(source_id, room_name)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            match source_id.resolve() {
+                Some(source) if creep.pos().is_near_to(source.pos()) => {
+                    // with a container for a remote hauler to empty, fill that instead of walking
+                    // the load home
+                    let harvest = creep.get_active_bodyparts(Part::Work) as u32 * HARVEST_POWER;
+                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) as u32 <= harvest {
+                        let container = source_container(&room, find_cache, &source)
+                            .filter(|container| container.store().get_free_capacity(Some(ResourceType::Energy)) > 0);
+                        if let Some(container) = container {
+                            handle_action(creep, container.pos(), creep.transfer(&container, ResourceType::Energy, None), target, "fill remote container");
+                        }
+                    }
+                    creep.harvest(&source).unwrap_or_else(|e| match e {
+                        // source is depleted, wait for it to regenerate
+                        ErrorCode::NotEnough => {}
+                        _ => {
+                            warn!("couldn't harvest remote source: {:?}", e);
+                            *target = None;
+                        }
+                    });
+                }
+                Some(source) => {
+                    let _ = movement::move_to(creep, &source);
+                }
+                // the source can't be resolved until the room is in sight
+                None if creep.pos().room_name() != *room_name => {
+                    let _ = movement::move_to(creep, room_center(*room_name));
+                }
+                None => *target = None,
+            }
+        }
+        Some(CreepTarget::CollectRemote(source_id, room_name)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            match source_id.resolve() {
+                Some(source) if creep.pos().room_name() == *room_name => {
+                    let source_pos = source.pos();
+                    let container = source_container(&room, find_cache, &source)
+                        .filter(|container| container.store().get_used_capacity(Some(ResourceType::Energy)) > 0);
+                    // rather than waiting on an empty container, take whatever's spilled around it
+                    let pile = find_cache
+                        .find(&room, find::DROPPED_RESOURCES)
+                        .into_iter()
+                        .filter(|resource| {
+                            resource.resource_type() == ResourceType::Energy && resource.pos().in_range_to(source_pos, REMOTE_PICKUP_RANGE)
+                        })
+                        .max_by_key(|resource| resource.amount());
+                    if let Some(container) = container {
+                        handle_action(creep, container.pos(), creep.withdraw(&container, ResourceType::Energy, None), target, "withdraw from remote container");
+                    } else if let Some(pile) = pile {
+                        handle_action(creep, pile.pos(), creep.pickup(&pile), target, "pick up");
+                    } else if !creep.pos().in_range_to(source_pos, REMOTE_PICKUP_RANGE) {
+                        let _ = movement::move_to(creep, source_pos);
+                    }
+                }
+                Some(source) => {
+                    let _ = movement::move_to(creep, &source);
+                }
+                // the source can't be resolved until the room is in sight
+                None if creep.pos().room_name() != *room_name => {
+                    let _ = movement::move_to(creep, room_center(*room_name));
+                }
+                None => *target = None,
+            }
+        }
+        Some(CreepTarget::DepositStorage(storage_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            match storage_id.resolve() {
+                Some(storage) if storage_accepts_deposit(&storage) => {
+                    handle_action(creep, storage.pos(), creep.transfer(&storage, ResourceType::Energy, None), target, "deposit energy");
+                }
+                // gone, full or over the cap
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::DropEnergy(pos)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if !creep.pos().in_range_to(*pos, COLLECTION_POINT_RANGE) {
+                let _ = movement::move_to(creep, *pos);
+            } else {
+                if let Err(e) = creep.drop(ResourceType::Energy, None) {
+                    warn!("couldn't drop energy: {:?}", e);
+                }
+                *target = None;
+            }
+        }
+        // dismantling fills the store, so once full this falls through to picking a delivery
+        Some(CreepTarget::Dismantle(structure_id)) if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(structure) = structure_id.resolve().map(StructureObject::from) {
+                if let Some(dismantleable) = structure.as_dismantleable() {
+                    handle_action(creep, structure.pos(), creep.dismantle(dismantleable), target, "dismantle");
+                } else {
+                    *target = None;
+                }
+            } else {
+                // torn down
+                *target = None;
+            }
+        }
+        Some(CreepTarget::Repair(structure_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            if let Some(structure) = structure_id.resolve().map(StructureObject::from) {
+                let under_attack = structure.as_structure().room().is_some_and(|room| under_attack(&room, find_cache));
+                match (structure.as_repairable(), defense_repair_goal(&structure, under_attack)) {
+                    (Some(repairable), Some(goal)) if structure.as_structure().hits() < goal => {
+                        handle_action(creep, structure.pos(), creep.repair(repairable), target, "repair");
+                    }
+                    // fully repaired (or not repairable at all)
+                    _ => *target = None,
+                }
+            } else {
+                *target = None;
+            }
+        }
+        Some(CreepTarget::Fortify(structure_id)) if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 => {
+            say_role(creep, role);
+            let structure = structure_id.resolve().map(StructureObject::from);
+            let goal = fortify_goal(&room);
+            match structure {
+                Some(structure) if structure.as_structure().hits() < goal => {
+                    let hits = structure.as_structure().hits();
+                    // keep the defences growing evenly rather than maxing out one wall
+                    let weaker = weakest_barrier(&room, find_cache)
+                        .filter(|weakest| weakest.as_structure().hits() + FORTIFY_SWITCH_MARGIN < hits);
+                    if let Some(weakest) = weaker {
+                        *target = Some(CreepTarget::Fortify(weakest.as_structure().id()));
+                    } else if let Some(repairable) = structure.as_repairable() {
+                        handle_action(creep, structure.pos(), creep.repair(repairable), target, "fortify");
+                    } else {
+                        *target = None;
+                    }
+                }
+                // built up as far as the controller level calls for, or gone
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::HoldEnergy(spawn_id)) => {
+            say_role(creep, role);
+            match spawn_id.resolve() {
+                Some(spawn)
+                    if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+                        && room.energy_available() >= room.energy_capacity_available() =>
+                {
+                    if !creep.pos().in_range_to(spawn.pos(), PRELOAD_RANGE) {
+                        let _ = movement::move_to(creep, &spawn);
+                    }
+                }
+                // the spawn's been drawing on its energy, so deliver it; or there's nothing to hold
+                _ => *target = None,
+            }
+        }
+        Some(CreepTarget::MoveToFlag(flag_name)) => {
+            say_role(creep, role);
+            match game::flags().get(flag_name.clone()) {
+                Some(flag) => {
+                    // hold on the flag once there
+                    if creep.pos() != flag.pos() {
+                        let _ = movement::move_to(creep, flag.pos());
+                    }
+                }
+                None => {
+                    info!("flag {} is gone, releasing {}", flag_name, creep.name());
+                    clear_flag_assignment(creep);
+                    *target = None;
+                }
+            }
+        }
+        Some(CreepTarget::Idle { pos, until }) if game::time() < *until => {
+            if !creep.pos().in_range_to(*pos, IDLE_RANGE) {
+                let _ = movement::move_to(creep, *pos);
+            } else if let Some(room) = creep.room().filter(|room| standing_on_structure(creep, room, find_cache)) {
+                // roads and the tiles around the spawn are for creeps with somewhere to be
+                if let Some(free) = free_idle_tile(creep, &room, find_cache, *pos) {
+                    let _ = movement::move_to(creep, free);
+                }
+            }
+        }
+        _ => {
+            // No target or invalid target, find a new one
+            searched = true;
+            // anything that can fight drops what it's doing to take on hostiles in the room;
+            // defenders go for whoever's closest to the spawn
+            let hostile_id = if *role == CreepRole::Defender {
+                hostile_nearest_spawn(&room, find_cache).and_then(|hostile| hostile.try_id())
+            } else if creep.get_active_bodyparts(Part::Attack) > 0 {
+                find_cache
+                    .find(&room, find::HOSTILE_CREEPS)
+                    .into_iter()
+                    .min_by_key(|hostile| creep.pos().get_range_to(hostile.pos()))
+                    .and_then(|hostile| hostile.try_id())
+            } else {
+                None
+            };
+
+            let ranged_hostile_id = if creep.get_active_bodyparts(Part::RangedAttack) > 0 {
+                ranged_attack_target(creep, &room, find_cache).and_then(|hostile| hostile.try_id())
+            } else {
+                None
+            };
+
+            if let Some(hostile_id) = hostile_id {
+                *target = Some(CreepTarget::Attack(hostile_id));
+                say_role(creep, role);
+            } else if let Some(hostile_id) = ranged_hostile_id {
+                *target = Some(CreepTarget::RangedAttack(hostile_id));
+                say_role(creep, role);
+            } else if let Some(power) = power_bank_target(creep, role, &room, find_cache) {
+                *target = Some(power);
+                say_role(creep, role);
+            } else if creep.get_active_bodyparts(Part::Heal) > 0 {
+                // healers look after the fighters before anyone else
+                let patient = if *role == CreepRole::Healer {
+                    most_damaged_fighter(&room, find_cache).or_else(|| most_damaged_creep(&room, find_cache))
+                } else {
+                    most_damaged_creep(&room, find_cache)
+                };
+                let shadowed = if *role == CreepRole::Healer {
+                    fighter_nearest_hostile(creep, &room, find_cache).or_else(|| closest_fighter(creep, &room, find_cache))
+                } else {
+                    closest_fighter(creep, &room, find_cache)
+                };
+                if let Some(patient_id) = patient.and_then(|patient| patient.try_id()) {
+                    *target = Some(CreepTarget::Heal(patient_id));
+                    say_role(creep, role);
+                } else if let Some(fighter) = shadowed {
+                    // nobody hurt yet, stay close to whoever's going to be
+                    if !creep.pos().is_near_to(fighter.pos()) {
+                        let _ = movement::move_to(creep, &fighter);
+                    }
+                }
+            } else if let Some(claim) = (creep.get_active_bodyparts(Part::Claim) > 0).then(claim_target).flatten() {
+                *target = Some(claim);
+                say_role(creep, role);
+            } else if let Some(reserve) = (creep.get_active_bodyparts(Part::Claim) > 0)
+                .then(|| reserve_target(claimed))
+                .flatten()
+            {
+                *target = Some(reserve);
+                say_role(creep, role);
+            } else if let CreepRole::Claimer = role {
+                // nothing left to claim or reserve; falls through to idling
+            } else if let CreepRole::MineralMiner = role {
+                // it only ever carries what it digs up
+                let carried = creep.store().store_types().into_iter().find(|&resource| resource != ResourceType::Energy);
+                let mineral = harvestable_mineral(&room, find_cache)
+                    .filter(|_| creep.store().get_free_capacity(None) > 0);
+                if let Some(mineral) = mineral {
+                    *target = Some(CreepTarget::HarvestMineral(mineral.id()));
+                    say_role(creep, role);
+                } else if let Some(resource) = carried {
+                    // the container right by the mineral saves the walk, haulers take it from there
+                    let container = mineral_container(&room, find_cache)
+                        .filter(|container| container.store().get_free_capacity(None) > 0)
+                        .map(StructureObject::from);
+                    if let Some(structure) = container.or_else(|| mineral_deposit(&room)) {
+                        *target = Some(CreepTarget::DepositMineral(structure.as_structure().id(), resource));
+                        say_role(creep, role);
+                    }
+                }
+            } else if let CreepRole::DepositMiner = role {
+                if room.controller().is_none() {
+                    // keep what's known about the highway current, so played out deposits stop
+                    // drawing us back
+                    let observed = RoomIntel::observe(&room, find_cache);
+                    ROOM_INTEL.with(|room_intel_refcell| room_intel_refcell.borrow_mut().insert(room.name(), observed));
+                }
+                let carried = creep.store().store_types().into_iter().next();
+                let has_space = creep.store().get_free_capacity(None) > 0;
+                let deposit = find_cache
+                    .find(&room, find::DEPOSITS)
+                    .into_iter()
+                    .find(|deposit| has_space && deposit.last_cooldown() <= intel::DEPOSIT_MAX_COOLDOWN);
+                if let Some(deposit) = deposit {
+                    *target = Some(CreepTarget::HarvestDeposit(deposit.id()));
+                    say_role(creep, role);
+                } else if let Some(resource) = carried {
+                    // home first, then into the terminal
+                    let next = haul_home(creep).or_else(|| {
+                        mineral_deposit(&room)
+                            .map(|structure| CreepTarget::DepositMineral(structure.as_structure().id(), resource))
+                    });
+                    if let Some(next) = next {
+                        *target = Some(next);
+                        say_role(creep, role);
+                    }
+                } else if let Some(room_name) =
+                    ROOM_INTEL.with(|room_intel_refcell| intel::deposit_room(room.name(), &room_intel_refcell.borrow()))
+                {
+                    *target = Some(CreepTarget::MoveToRoom(room_name));
+                    say_role(creep, role);
+                }
+            } else if let CreepRole::RemoteHarvester = role {
+                if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+                    // home first, then into storage or wherever else it's needed
+                    let next = haul_home(creep).or_else(|| delivery_target(creep, &room, find_cache, claimed, true));
+                    if let Some(next) = next {
+                        *target = Some(next);
+                        say_role(creep, role);
+                    }
+                } else if let Some((source_id, room_name)) = remote_harvest_source(creep, claimed) {
+                    *target = Some(CreepTarget::RemoteHarvest(source_id, room_name));
+                    say_role(creep, role);
+                }
+            } else if let CreepRole::RemoteHauler(source_id, room_name) = role {
+                if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+                    // home first, then into storage or wherever else it's needed
+                    let next = haul_home(creep).or_else(|| delivery_target(creep, &room, find_cache, claimed, true));
+                    if let Some(next) = next {
+                        *target = Some(next);
+                        say_role(creep, role);
+                    }
+                } else if !ROOM_INTEL.with(|room_intel_refcell| intel::is_dangerous(*room_name, &room_intel_refcell.borrow())) {
+                    // the source it was spawned for is the only one it ever hauls from, once any
+                    // invaders there have had time to leave
+                    *target = Some(CreepTarget::CollectRemote(*source_id, *room_name));
+                    say_role(creep, role);
+                }
+            } else if let CreepRole::Scout = role {
+                let next = ROOM_INTEL.with(|room_intel_refcell| {
+                    intel::next_scout_room(room.name(), &mut room_intel_refcell.borrow_mut())
+                });
+                if let Some(room_name) = next {
+                    *target = Some(CreepTarget::Explore(room_name));
+                    say_role(creep, role);
+                }
+            } else if let CreepRole::StaticMiner(source_id, mining_pos) = role {
+                // the spot it was spawned for is the only one it ever mines
+                *target = Some(CreepTarget::Mine(*source_id, *mining_pos));
+                say_role(creep, role);
+            } else if let CreepRole::Miner = role {
+                // miners never take delivery targets, only a spot next to a source; big enough ones
+                // get a source and its container to themselves
+                let drop_source = (creep.get_active_bodyparts(Part::Work) >= DROP_MINE_WORK_PARTS)
+                    .then(|| {
+                        let creep_pos = creep.pos();
+                        find_cache
+                            .find(&room, find::SOURCES)
+                            .into_iter()
+                            .filter(|source| !drop_mined.contains(&source.id()) && !keeper_guarded(&room, find_cache, source))
+                            .min_by_key(|source| creep_pos.get_range_to(source.pos()))
+                    })
+                    .flatten();
+                if let Some(source) = drop_source {
+                    *target = Some(CreepTarget::DropMine(source.id()));
+                    say_role(creep, role);
+                } else if let Some((source, mining_pos)) = balanced_mining_spot(creep, &room, find_cache, &taken_spots) {
+                    *target = Some(CreepTarget::Mine(source.id(), mining_pos));
+                    say_role(creep, role);
+                } else {
+                    warn!("no free tile next to any source in {}", room.name());
+                }
+            } else if let CreepRole::Defender | CreepRole::RangedDefender = role {
+                // nothing to fight; hold the edge the last attack came from for a while, then hand
+                // the energy back
+                let threat = LAST_THREAT.with(|last_threat_refcell| last_threat_refcell.borrow().get(&room.name()).copied());
+                match threat.filter(|(_, seen)| game::time() < seen + DEFENDER_GRACE_TICKS) {
+                    Some((pos, seen)) => {
+                        *target = Some(CreepTarget::Idle { pos: edge_post(pos), until: seen + DEFENDER_GRACE_TICKS });
+                        say_role(creep, role);
+                    }
+                    None => {
+                        let spawn = find_cache
+                            .find(&room, find::MY_SPAWNS)
+                            .into_iter()
+                            .min_by_key(|spawn| creep.pos().get_range_to(spawn.pos()));
+                        if let Some(spawn) = spawn {
+                            info!("threat over in {}, recycling defender {}", room.name(), creep.name());
+                            *target = Some(CreepTarget::Recycle(spawn.id()));
+                            say_role(creep, role);
+                        }
+                    }
+                }
+            } else if let Some((miner, destination)) = stranded
+                .iter()
+                .filter(|(miner, _)| {
+                    miner.room().is_some_and(|miner_room| miner_room.name() == room.name())
+                        && !miner.try_id().is_some_and(|id| claimed.contains(&id.into()))
+                })
+                .min_by_key(|(miner, _)| creep.pos().get_range_to(miner.pos()))
+            {
+                // only haulers are ever handed miners to tow
+                if let Some(id) = miner.try_id() {
+                    *target = Some(CreepTarget::Pull(id, *destination));
+                    say_role(creep, role);
+                }
+            } else if let Some(collect) = (*role == CreepRole::Hauler && creep.store().get_used_capacity(None) == 0)
+                .then(|| power_haul_target(creep, claimed))
+                .flatten()
+            {
+                *target = Some(collect);
+                say_role(creep, role);
+            } else if let Some(plunder) = (*role == CreepRole::Hauler && creep.store().get_used_capacity(None) == 0)
+                .then(|| plunder_target(claimed))
+                .flatten()
+            {
+                *target = Some(plunder);
+                say_role(creep, role);
+            } else if let CreepRole::Hauler = role {
+                // anything other than energy was taken out for a lab
+                let reagent = creep.store().store_types().into_iter().find(|&resource| resource != ResourceType::Energy);
+                // haulers fill up from piles and containers, then deliver like workers
+                let pickup = if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
+                    hauler_pickup_target(creep, &room, find_cache)
+                } else {
+                    None
+                };
+                if let Some(reagent) = reagent {
+                    // put it back if nothing wants it any more
+                    let next = stock_target(&room, find_cache, reagent).or_else(|| {
+                        mineral_deposit(&room)
+                            .map(|structure| CreepTarget::DepositMineral(structure.as_structure().id(), reagent))
+                    });
+                    if let Some(next) = next {
+                        *target = Some(next);
+                        say_role(creep, role);
+                    }
+                } else if let Some(hub) = link::hub_link(&room).filter(|hub| {
+                    creep.store().get_used_capacity(None) == 0
+                        && hub.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+                        && !claimed.contains(&hub.id().into())
+                }) {
+                    // emptied before anything else, so the source links always have somewhere to send
+                    *target = Some(CreepTarget::WithdrawLink(hub.id()));
+                    say_role(creep, role);
+                } else if let Some(supply) = safe_mode_target(creep, &room, claimed)
+                    .or_else(|| lab_supply_target(&room))
+                    .or_else(|| nuker_supply_target(&room, find_cache))
+                    .or_else(|| power_spawn_supply_target(&room, find_cache))
+                    .or_else(|| mineral_container_pickup(creep, &room, find_cache))
+                    .or_else(|| terminal_supply_target(creep, &room))
+                    .filter(|_| creep.store().get_used_capacity(None) == 0)
+                {
+                    // only ever fetched on an empty trip, so reagents never share a store with energy
+                    *target = Some(supply);
+                    say_role(creep, role);
+                } else if let Some(pickup) = pickup {
+                    *target = Some(pickup);
+                    say_role(creep, role);
+                } else if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+                    // the controller and upkeep need WORK parts, which haulers don't have
+                    let delivery = delivery_target(creep, &room, find_cache, claimed, true)
+                        .filter(|delivery| delivery.required_parts().iter().all(|&part| creep.get_active_bodyparts(part) > 0));
+                    // labs, the nuker and the power spawn come after spawning and towers, but
+                    // before energy is banked or upgraded with
+                    let extra = match delivery {
+                        Some(CreepTarget::DepositStorage(_) | CreepTarget::Upgrade(_)) | None => {
+                            controller_container_needing(&room, find_cache, claimed)
+                                .map(|container| CreepTarget::FillControllerContainer(container.id()))
+                                .or_else(|| stock_target(&room, find_cache, ResourceType::Energy))
+                        }
+                        _ => None,
+                    };
+                    // haulers can't upgrade, so without storage one that's full or has nothing left to
+                    // pick up leaves its energy for the workers
+                    let nowhere_else = extra.is_none() && delivery.is_none();
+                    // with the spawn and extensions full, a couple hang on to theirs for the next refill
+                    // rather than banking or dropping it
+                    let hold = if extra.is_none()
+                        && matches!(delivery, Some(CreepTarget::DepositStorage(_)) | None)
+                        && holding < PRELOAD_HAULERS
+                        && room.energy_available() >= room.energy_capacity_available()
+                    {
+                        find_cache
+                            .find(&room, find::MY_SPAWNS)
+                            .into_iter()
+                            .min_by_key(|spawn| creep.pos().get_range_to(spawn.pos()))
+                            .map(|spawn| CreepTarget::HoldEnergy(spawn.id()))
+                    } else {
+                        None
+                    };
+                    let drop = if nowhere_else && room.storage().is_none() {
+                        collection_point(&room).map(CreepTarget::DropEnergy)
+                    } else {
+                        None
+                    };
+                    if let Some(delivery) = hold.or(drop).or(extra).or(delivery) {
+                        *target = Some(delivery);
+                        say_role(creep, role);
+                    }
+                }
+            } else if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+                match role {
+                    CreepRole::Builder => {
+                        if let Some(fill) = emergency_fill_target(creep, &room, find_cache, claimed) {
+                            *target = Some(fill);
+                            say_role(creep, role);
+                        } else if let Some(site) = find_cache.build_site(&room, |find_cache| priority_site(&room, find_cache)) {
+                            if let Some(id) = site.try_id() {
+                                *target = Some(CreepTarget::Build(id));
+                                say_role(creep, role);
+                            } else {
+                                warn!("Construction site has no id");
+                            }
+                        } else if let Some(structure) = find_cache
+                            .find(&room, find::STRUCTURES)
+                            .into_iter()
+                            .find(needs_repair)
+                        {
+                            *target = Some(CreepTarget::Repair(structure.as_structure().id()));
+                            say_role(creep, role);
+                        } else if let Some(upgrade) = upgrade_target(&room, find_cache) {
+                            *target = Some(upgrade);
+                            say_role(creep, role);
+                        }
+                    }
+                    CreepRole::Repairer => {
+                        if let Some(fill) = emergency_fill_target(creep, &room, find_cache, claimed) {
+                            *target = Some(fill);
+                            say_role(creep, role);
+                        } else if let Some(rampart) = weakest_defense_rampart(&room, find_cache) {
+                            *target = Some(CreepTarget::Repair(rampart.as_structure().id()));
+                            say_role(creep, role);
+                        } else if let Some(structure) = busiest_decayed_infrastructure(&room, find_cache) {
+                            *target = Some(CreepTarget::Repair(structure.as_structure().id()));
+                            say_role(creep, role);
+                        } else if let Some(structure) = most_damaged_structure(&room, find_cache) {
+                            *target = Some(CreepTarget::Repair(structure.as_structure().id()));
+                            say_role(creep, role);
+                        } else if let Some(barrier) = weakest_barrier(&room, find_cache) {
+                            *target = Some(CreepTarget::Fortify(barrier.as_structure().id()));
+                            say_role(creep, role);
+                        } else if let Some(id) = find_cache
+                            .build_site(&room, |find_cache| priority_site(&room, find_cache))
+                            .and_then(|site| site.try_id())
+                        {
+                            // nothing to fix, help the builders out
+                            *target = Some(CreepTarget::Build(id));
+                            say_role(creep, role);
+                        } else if let Some(upgrade) = upgrade_target(&room, find_cache) {
+                            // nothing to fix or build, don't sit on the energy
+                            *target = Some(upgrade);
+                            say_role(creep, role);
+                        }
+                    }
+                    CreepRole::Upgrader => {
+                        if let Some(controller) = room.controller().filter(|controller| controller.my()) {
+                            *target = Some(CreepTarget::Upgrade(controller.id()));
+                            say_role(creep, role);
+                        }
+                    }
+                    CreepRole::Worker => {
+                        // the old target is still around here; energy that just came out of
+                        // storage shouldn't be put straight back in
+                        let from_storage = matches!(target, Some(CreepTarget::WithdrawStorage(_)));
+                        match delivery_target(creep, &room, find_cache, claimed, !from_storage) {
+                            // with nothing better to do than upgrade, put our name on the controller first
+                            Some(CreepTarget::Upgrade(controller_id)) if needs_sign(&room, claimed) => {
+                                *target = Some(CreepTarget::Sign(controller_id));
+                                say_role(creep, role);
+                            }
+                            Some(delivery) => {
+                                *target = Some(delivery);
+                                say_role(creep, role);
+                            }
+                            None => {}
+                        }
+                    }
+                    // handled above
+                    CreepRole::Miner
+                    | CreepRole::StaticMiner(..)
+                    | CreepRole::Hauler
+                    | CreepRole::Defender
+                    | CreepRole::RangedDefender
+                    | CreepRole::Healer
+                    | CreepRole::Scout
+                    | CreepRole::MineralMiner
+                    | CreepRole::DepositMiner
+                    | CreepRole::PowerAttacker
+                    | CreepRole::PowerHealer
+                    | CreepRole::Claimer
+                    | CreepRole::RemoteHarvester
+                    | CreepRole::RemoteHauler(..) => {}
+                }
+            } else if let Some(structure) = dismantle_target(creep, &room, find_cache) {
+                *target = Some(CreepTarget::Dismantle(structure.as_structure().id()));
+                say_role(creep, role);
+            } else if let Some(container) = controller_container(&room, find_cache).filter(|container| {
+                (matches!(target, Some(CreepTarget::Upgrade(_))) || *role == CreepRole::Upgrader)
+                    && container.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+            }) {
+                // upgraders refill right next to where they work
+                *target = Some(CreepTarget::WithdrawContainer(container.id()));
+                say_role(creep, role);
+            } else if let Some(pile) = largest_energy_pile(creep, &room, find_cache, PICKUP_MIN_AMOUNT) {
+                *target = Some(CreepTarget::Pickup(pile.id()));
+                say_role(creep, role);
+            } else if let Some(tombstone) = lootable_tombstone(creep, &room, find_cache, false) {
+                // tombstones decay, so they come before anything that will still be there later
+                *target = Some(CreepTarget::LootTombstone(tombstone.id()));
+                say_role(creep, role);
+            } else if let Some(ruin) = lootable_ruin(creep, &room, find_cache, false) {
+                *target = Some(CreepTarget::LootRuin(ruin.id()));
+                say_role(creep, role);
+            } else if let Some(storage) = room.storage().filter(|storage| {
+                // the reserve under the floor is only for workers refilling the spawns; everyone
+                // else falls back on containers and the sources until it's built back up
+                let energy = storage.store().get_used_capacity(Some(ResourceType::Energy));
+                energy > STORAGE_ENERGY_FLOOR || (energy > 0 && *role == CreepRole::Worker)
+            }) {
+                *target = Some(CreepTarget::WithdrawStorage(storage.id()));
+                say_role(creep, role);
+            } else if let Some(container) = closest_energy_container(
+                creep,
+                &room,
+                find_cache,
+                creep.store().get_free_capacity(Some(ResourceType::Energy)).max(0) as u32,
+            ) {
+                // a container that can fill us up beats walking on to harvest
+                *target = Some(CreepTarget::WithdrawContainer(container.id()));
+                say_role(creep, role);
+            } else if let Some(source) = least_crowded_source(creep, safe_sources(&room, find_cache, find::SOURCES_ACTIVE), source_crowds) {
+                *target = Some(CreepTarget::Harvest(source.id()));
+                say_role(creep, role);
+            } else if let Some(source) = closest_unclaimed(
+                creep,
+                safe_sources(&room, find_cache, find::SOURCES)
+                    .into_iter()
+                    .filter(|source| source.energy() == 0 && worth_waiting_for(creep, source))
+                    .collect(),
+                claimed,
+                |source| Some(source.id().into()),
+            ) {
+                // every source is dry, so wait by one rather than wandering off
+                *target = Some(CreepTarget::WaitForSource(source.id()));
+                say_role(creep, role);
+            }
+        }
+    }
+
+    // a path cached on the way to the old target is no use for getting to the new one
+    if *target != target_before {
+        movement::forget_path(creep);
+    }
+
+    // never hold on to a newly picked target the body can't work
+    if target.as_ref().is_some_and(|target| !has_parts_for(creep, target)) {
+        *target = None;
+    }
+
+    // nothing to do, so get out of the way rather than looking again every tick; defenders
+    // have their own post by the spawn, and healers keep close to them
+    if searched
+        && target.is_none()
+        && !matches!(role, CreepRole::Defender | CreepRole::RangedDefender | CreepRole::Healer | CreepRole::PowerHealer)
+    {
+        if let Some(pos) = creep.room().and_then(|room| idle_spot(&room)) {
+            *target = Some(CreepTarget::Idle { pos, until: game::time() + IDLE_TICKS });
+        }
+    }
+
+    if let Some(id) = target.as_ref().and_then(CreepTarget::claimed_id) {
+        claimed.insert(id);
+    }
+    if *target != target_before {
+        if let Some(CreepTarget::Harvest(source_id) | CreepTarget::Mine(source_id, _) | CreepTarget::DropMine(source_id)) = target {
+            *source_crowds.entry(*source_id).or_default() += 1;
+        }
+    }
+}
+
+// Whether the creep is close enough to dying, and its body valuable and current enough,
+// that it should be renewed instead of replaced
+fn worth_renewing(creep: &Creep, role: &CreepRole, room: &Room) -> bool {
+    let body = creep.body();
+    let threshold = RENEW_TTL_THRESHOLD + body.len() as u32 * RENEW_TTL_PER_PART;
+    let cost = creep_body_cost(creep);
+    creep.ticks_to_live().is_some_and(|ticks| ticks < threshold)
+        && cost >= RENEW_MIN_BODY_COST
+        // smaller than what the room spawns for the role now; let it die and get a proper one
+        && cost >= body_cost(&spawned_body(room.energy_capacity_available(), role))
+        // spawns refuse to renew anything with CLAIM parts
+        && body.iter().all(|part| part.part() != Part::Claim)
+        // static miners get replaced on schedule instead
+        && !matches!(role, CreepRole::StaticMiner(..))
+}
+
+// Whether the creep is far smaller than what the room spawns for its role now, and the room
+// has the energy to replace it straight away
+fn body_obsolete(creep: &Creep, role: &CreepRole, room: &Room) -> bool {
+    let template_cost = body_cost(&spawned_body(room.energy_capacity_available(), role));
+    room.energy_available() >= template_cost && (creep_body_cost(creep) as f32) < template_cost as f32 * RECYCLE_COST_FRACTION
+}
+
+// Whether the creep's been flagged for recycling in its memory
+fn marked_for_recycling(creep: &Creep) -> bool {
+    Reflect::get(&creep.memory(), &JsString::from(RECYCLE_MEMORY_KEY)).is_ok_and(|flag| flag.as_bool() == Some(true))
+}
+
+// The flag the creep's been sent to in its memory, if any
+fn flag_assignment(creep: &Creep) -> Option<String> {
+    Reflect::get(&creep.memory(), &JsString::from(FLAG_MEMORY_KEY)).ok()?.as_string()
+}
+
+fn clear_flag_assignment(creep: &Creep) {
+    let memory = creep.memory();
+    if memory.is_object() {
+        let _ = Reflect::delete_property(memory.unchecked_ref::<Object>(), &JsString::from(FLAG_MEMORY_KEY));
+    }
+}
+
+fn creep_body_cost(creep: &Creep) -> u32 {
+    creep.body().iter().map(|part| part.part().cost()).sum()
+}
+
+// Recycle a creep waiting next to the spawn for it, forgetting about it straight away
+fn recycle_waiting_creep(spawn: &StructureSpawn, room: &Room, creep_info: &mut CreepInfoMap, find_cache: &mut FindCache) {
+    let waiting = find_cache
+        .find(room, find::MY_CREEPS)
+        .into_iter()
+        .filter(|creep| creep.pos().is_near_to(spawn.pos()))
+        .find(|creep| {
+            matches!(
+                creep_info.get(&creep.name()),
+                Some((_, Some(CreepTarget::Recycle(spawn_id)))) if *spawn_id == spawn.id()
+            )
+        });
+
+    if let Some(creep) = waiting {
+        match spawn.recycle_creep(&creep) {
+            Ok(()) => {
+                creep_info.remove(&creep.name());
+            }
+            Err(e) => warn!("couldn't recycle {}: {:?}", creep.name(), e),
+        }
+    }
+}
+
+// Renew the creep waiting next to the spawn with the least time left, if there is one
+fn renew_waiting_creep(spawn: &StructureSpawn, room: &Room, creep_info: &CreepInfoMap, find_cache: &mut FindCache) {
+    if spawn.spawning().is_some() {
+        return;
+    }
+
+    let waiting = find_cache
+        .find(room, find::MY_CREEPS)
+        .into_iter()
+        .filter(|creep| creep.pos().is_near_to(spawn.pos()))
+        .filter(|creep| {
+            matches!(
+                creep_info.get(&creep.name()),
+                Some((_, Some(CreepTarget::Renew(spawn_id, _)))) if *spawn_id == spawn.id()
+            )
+        })
+        .filter_map(|creep| Some((creep.ticks_to_live()?, creep)))
+        .filter(|(ticks, _)| *ticks < RENEW_TTL_TARGET)
+        .min_by_key(|(ticks, _)| *ticks);
+
+    if let Some((_, creep)) = waiting {
+        match spawn.renew_creep(&creep) {
+            // out of energy; the creep keeps waiting for it to come back
+            Ok(()) | Err(ErrorCode::NotEnough) => {}
+            Err(e) => warn!("couldn't renew {}: {:?}", creep.name(), e),
+        }
+    }
+}
+
+// Whether the creep has every part the target needs, warning the first time it doesn't
+fn has_parts_for(creep: &Creep, target: &CreepTarget) -> bool {
+    let missing: Vec<Part> = target
+        .required_parts()
+        .iter()
+        .copied()
+        .filter(|&part| creep.get_active_bodyparts(part) == 0)
+        .collect();
+    if missing.is_empty() {
+        return true;
+    }
+
+    BODY_WARNED.with(|body_warned_refcell| {
+        if body_warned_refcell.borrow_mut().insert(creep.name()) {
+            warn!("creep {} has no working {:?} parts, skipping targets that need them", creep.name(), missing);
+        }
+    });
+    false
+}
+
+// Whether to wait by a dry source: always with nothing to spend, otherwise only if it's back soon
+fn worth_waiting_for(creep: &Creep, source: &Source) -> bool {
+    creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0
+        || source
+            .ticks_to_regeneration()
+            .is_some_and(|ticks| ticks <= SOURCE_WAIT_MAX_TICKS)
+}
+
+// The closest candidate no creep has claimed yet, or the closest of them all if every one
+// is already taken
+fn closest_unclaimed<T: HasPosition>(
+    creep: &Creep,
+    candidates: Vec<T>,
+    claimed: &HashSet<RawObjectId>,
+    id: impl Fn(&T) -> Option<RawObjectId>,
+) -> Option<T> {
+    let creep_pos = creep.pos();
+    candidates.into_iter().min_by_key(|candidate| {
+        let taken = id(candidate).is_some_and(|id| claimed.contains(&id));
+        (taken, creep_pos.get_range_to(candidate.pos()))
+    })
+}
+
+// The hit count a structure should be repaired up to, or None if it can't be repaired
+fn repair_goal(structure: &StructureObject) -> Option<u32> {
+    let hits_max = structure.as_repairable()?.hits_max();
+    match structure.structure_type() {
+        StructureType::Wall | StructureType::Rampart => Some(hits_max.min(BARRIER_HITS_CAP)),
+        _ => Some(hits_max),
+    }
+}
+
+// How far along a structure is towards its repair goal, or None if it can't be repaired
+fn repair_fraction(structure: &StructureObject) -> Option<f32> {
+    let goal = repair_goal(structure)?;
+    Some(structure.as_structure().hits() as f32 / goal.max(1) as f32)
+}
+
+// The repair goal, except that barriers take everything they can get while the room is under attack
+fn defense_repair_goal(structure: &StructureObject, under_attack: bool) -> Option<u32> {
+    match structure.structure_type() {
+        StructureType::Wall | StructureType::Rampart if under_attack => Some(structure.as_repairable()?.hits_max()),
+        _ => repair_goal(structure),
+    }
+}
+
+fn needs_repair(structure: &StructureObject) -> bool {
+    repair_fraction(structure).is_some_and(|fraction| fraction < REPAIR_THRESHOLD)
+}
+
+// The structure needing repair that is furthest from its repair goal; walls and ramparts are
+// left to fortifying
+fn most_damaged_structure(room: &Room, find_cache: &mut FindCache) -> Option<StructureObject> {
+    find_cache
+        .find(room, find::STRUCTURES)
+        .into_iter()
+        .filter(|structure| !is_barrier(structure))
+        .filter_map(|structure| {
+            let fraction = repair_fraction(&structure).filter(|&fraction| fraction < REPAIR_THRESHOLD)?;
+            Some((structure, fraction))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(structure, _)| structure)
+}
+
+fn is_infrastructure(structure: &StructureObject) -> bool {
+    matches!(structure.structure_type(), StructureType::Road | StructureType::Container)
+}
+
+// Hits the room's roads and containers have lost
+fn decayed_hits(room: &Room, find_cache: &mut FindCache) -> u32 {
+    find_cache
+        .find(room, find::STRUCTURES)
+        .iter()
+        .filter(|structure| is_infrastructure(structure))
+        .filter_map(|structure| Some(structure.as_repairable()?.hits_max() - structure.as_structure().hits()))
+        .sum()
+}
+
+// The road or container under the patrol threshold that creeps walk over most, the most decayed
+// one of those
+fn busiest_decayed_infrastructure(room: &Room, find_cache: &mut FindCache) -> Option<StructureObject> {
+    TRAFFIC.with(|traffic_refcell| {
+        let traffic = traffic_refcell.borrow();
+        find_cache
+            .find(room, find::STRUCTURES)
+            .into_iter()
+            .filter(is_infrastructure)
+            .filter_map(|structure| {
+                let fraction = structure.as_structure().hits() as f32 / structure.as_repairable()?.hits_max().max(1) as f32;
+                let steps = traffic.get(&structure.pos()).copied().unwrap_or(0);
+                (fraction < INFRASTRUCTURE_REPAIR_THRESHOLD).then_some((structure, steps, fraction))
+            })
+            .max_by(|(_, a_steps, a_fraction), (_, b_steps, b_fraction)| {
+                a_steps.cmp(b_steps).then(b_fraction.total_cmp(a_fraction))
+            })
+            .map(|(structure, _, _)| structure)
+    })
+}
+
+// Count a footstep on the creep's tile, for telling busy roads from quiet ones
+fn record_traffic(creep: &Creep) {
+    if creep.spawning() {
+        return;
+    }
+    TRAFFIC.with(|traffic_refcell| *traffic_refcell.borrow_mut().entry(creep.pos()).or_default() += 1);
+}
+
+fn is_barrier(structure: &StructureObject) -> bool {
+    matches!(structure.structure_type(), StructureType::Wall | StructureType::Rampart)
+}
+
+fn fortify_goal(room: &Room) -> u32 {
+    let level = room.controller().map_or(0, |controller| controller.level() as usize);
+    FORTIFY_HITS[level.min(FORTIFY_HITS.len() - 1)]
+}
+
+// The wall or one of our ramparts with the fewest hits, if it's short of the controller level's goal
+fn weakest_barrier(room: &Room, find_cache: &mut FindCache) -> Option<StructureObject> {
+    let goal = fortify_goal(room);
+    find_cache
+        .find(room, find::STRUCTURES)
+        .into_iter()
+        .filter(|structure| match structure {
+            StructureObject::StructureWall(_) => true,
+            StructureObject::StructureRampart(rampart) => rampart.my(),
+            _ => false,
+        })
+        .filter(|barrier| barrier.as_structure().hits() < goal.min(barrier.as_structure().hits_max()))
+        .min_by_key(|barrier| barrier.as_structure().hits())
+}
+
+// The rampart with the fewest hits near one of the room's spawns, while there are hostiles around
+fn weakest_defense_rampart(room: &Room, find_cache: &mut FindCache) -> Option<StructureObject> {
+    if !under_attack(room, find_cache) {
+        return None;
+    }
+    let spawns = find_cache.find(room, find::MY_SPAWNS);
+    find_cache
+        .find(room, find::MY_STRUCTURES)
+        .into_iter()
+        .filter(|structure| {
+            structure.structure_type() == StructureType::Rampart
+                && spawns
+                    .iter()
+                    .any(|spawn| spawn.pos().in_range_to(structure.pos(), DEFENSE_RAMPART_RANGE))
+        })
+        .filter(|rampart| rampart.as_repairable().is_some_and(|repairable| rampart.as_structure().hits() < repairable.hits_max()))
+        .min_by_key(|rampart| rampart.as_structure().hits())
+}
+
+// Lower gets built first; ramparts and walls jump the queue while the room is under attack
+fn build_priority(structure_type: StructureType, under_attack: bool) -> u8 {
+    match structure_type {
+        StructureType::Wall | StructureType::Rampart if under_attack => 0,
+        StructureType::Spawn => 1,
+        StructureType::Tower => 2,
+        StructureType::Extension => 3,
+        StructureType::Container => 4,
+        StructureType::Storage => 5,
+        StructureType::Road => 7,
+        _ => 6,
+    }
+}
+
+// The construction site to build next: by priority, then the one closest to being finished
+fn priority_site(room: &Room, find_cache: &mut FindCache) -> Option<ConstructionSite> {
+    let under_attack = under_attack(room, find_cache);
+    let progress = |site: &ConstructionSite| site.progress() as f32 / site.progress_total().max(1) as f32;
+    find_cache
+        .find(room, find::CONSTRUCTION_SITES)
+        .into_iter()
+        .min_by(|a, b| {
+            build_priority(a.structure_type(), under_attack)
+                .cmp(&build_priority(b.structure_type(), under_attack))
+                .then(progress(b).total_cmp(&progress(a)))
+        })
+}
+
+fn body_cost(body: &[Part]) -> u32 {
+    body.iter().map(|p| p.cost()).sum()
+}
+
+// The biggest body for the role that `capacity` energy can pay for
+fn build_body(capacity: u32, role: &CreepRole) -> Vec<Part> {
+    match role {
+        // a CARRY part lets a drop miner build its own container, once the room can afford it
+        CreepRole::Miner if capacity >= body_cost(&MINER_BODY) + Part::Carry.cost() => {
+            [&MINER_BODY[..], &[Part::Carry]].concat()
+        }
+        CreepRole::Miner | CreepRole::StaticMiner(..) => MINER_BODY.to_vec(),
+        // no WORK at all, so nothing ever has them harvesting or upgrading instead of hauling
+        CreepRole::Hauler => scaled_body(&[], &[Part::Carry, Part::Carry, Part::Move], capacity),
+        CreepRole::Builder
+        | CreepRole::Worker
+        | CreepRole::Repairer
+        | CreepRole::DepositMiner
+        | CreepRole::RemoteHarvester => scaled_body(&[], &[Part::Move, Part::Carry, Part::Work], capacity),
+        CreepRole::RemoteHauler(source_id, _) => {
+            // enough CARRY to take everything the source puts out over one round trip
+            let round_trip = 2 * remote_path_length(*source_id);
+            let carry = (SOURCE_ENERGY_CAPACITY / ENERGY_REGEN_TIME * round_trip).div_ceil(CARRY_CAPACITY).max(1);
+            let unit = [Part::Carry, Part::Move];
+            scaled_body(&[], &unit, capacity.min(body_cost(&unit) * carry))
+        }
+        CreepRole::Upgrader => {
+            // no more WORK than a max level controller takes in a tick; past that it's better
+            // spent on another upgrader
+            let fixed = [Part::Carry, Part::Move];
+            let unit = [Part::Work, Part::Work, Part::Move];
+            let max_cost = body_cost(&fixed) + body_cost(&unit) * (CONTROLLER_MAX_UPGRADE_PER_TICK / 2);
+            scaled_body(&fixed, &unit, capacity.min(max_cost))
+        }
+        CreepRole::Defender => {
+            let mut body = scaled_body(&[], &[Part::Tough, Part::Attack, Part::Move, Part::Move], capacity);
+            // TOUGH parts soak up damage first when they're at the front
+            body.sort_by_key(|part| *part != Part::Tough);
+            body
+        }
+        CreepRole::RangedDefender => scaled_body(&[], &[Part::RangedAttack, Part::Move], capacity),
+        CreepRole::Healer | CreepRole::PowerHealer => scaled_body(&[], &[Part::Heal, Part::Move], capacity),
+        CreepRole::PowerAttacker => scaled_body(&[], &[Part::Attack, Part::Move], capacity),
+        // scouts only ever need to get places
+        CreepRole::Scout => vec![Part::Move],
+        CreepRole::MineralMiner => scaled_body(&[], &[Part::Work, Part::Work, Part::Carry, Part::Move], capacity),
+        CreepRole::Claimer => CLAIMER_BODY.to_vec(),
+    }
+}
+
+// Energy that has to be left over after spawning a creep of the role: enough for the smallest
+// defender if it's an economy creep, except while bootstrapping or in rooms too small to hold both
+fn defense_reserve(role: &CreepRole, capacity: u32, bootstrap: bool) -> u32 {
+    if bootstrap || !matches!(role, CreepRole::Worker | CreepRole::Builder | CreepRole::Repairer | CreepRole::Upgrader) {
+        return 0;
+    }
+    let reserve = body_cost(&build_body(0, &CreepRole::Defender));
+    if capacity < body_cost(&build_body(0, role)) + reserve {
+        return 0;
+    }
+    reserve
+}
+
+// The body a room with `capacity` spawns for the role once it's up and running
+fn spawned_body(capacity: u32, role: &CreepRole) -> Vec<Part> {
+    build_body(capacity - defense_reserve(role, capacity, false), role)
+}
+
+// `fixed` followed by as many copies of `unit` as the energy pays for, with at least one copy
+// even if it's unaffordable, then trimmed to the part limit
+fn scaled_body(fixed: &[Part], unit: &[Part], capacity: u32) -> Vec<Part> {
+    let budget = capacity.saturating_sub(body_cost(fixed));
+    let units = ((budget / body_cost(unit)) as usize).max(1);
+
+    let mut body = fixed.to_vec();
+    body.extend(unit.repeat(units));
+    fit_part_limit(body, fixed.len(), unit)
+}
+
+// A body over the part limit won't spawn at all; drop whole copies of `unit` from the end until
+// it fits, so the ratio between the parts holds
+fn fit_part_limit(mut body: Vec<Part>, fixed_len: usize, unit: &[Part]) -> Vec<Part> {
+    let original = body.len();
+    // never drop the last copy; the role might not work without it
+    while body.len() > MAX_CREEP_SIZE as usize && body.len() > fixed_len + unit.len() {
+        body.truncate(body.len() - unit.len());
+    }
+    if body.len() < original {
+        TRIM_WARNED.with(|trim_warned_refcell| {
+            if trim_warned_refcell.borrow_mut().insert(unit.to_vec()) {
+                warn!("{:?} body had {} parts, over the limit; trimmed to {}", unit, original, body.len());
+            }
+        });
+    }
+    body
+}
+
+fn count_role_in_room(
+    room: &Room,
+    creep_info: &CreepInfoMap,
+    role: &CreepRole,
+) -> usize {
+    game::creeps()
+        .values()
+        .filter(|creep| creep.room().is_some_and(|r| r.name() == room.name()))
+        .filter(|creep| creep_info.get(&creep.name()).is_some_and(|(r, _)| r == role))
+        .count()
+}
+
+fn count_role(creep_info: &CreepInfoMap, role: &CreepRole) -> usize {
+    game::creeps()
+        .values()
+        .filter(|creep| creep_info.get(&creep.name()).is_some_and(|(r, _)| r == role))
+        .count()
+}
+
+// Remote haulers bound to the source
+fn count_remote_haulers(creep_info: &CreepInfoMap, source_id: ObjectId<Source>) -> usize {
+    game::creeps()
+        .values()
+        .filter(|creep| {
+            creep_info
+                .get(&creep.name())
+                .is_some_and(|(role, _)| matches!(role, CreepRole::RemoteHauler(id, _) if *id == source_id))
+        })
+        .count()
+}
+
+// Static miners bound to the source that aren't about to die of old age; one still spawning
+// has its whole life ahead of it
+fn count_static_miners(creep_info: &CreepInfoMap, source_id: ObjectId<Source>) -> usize {
+    game::creeps()
+        .values()
+        .filter(|creep| {
+            creep_info
+                .get(&creep.name())
+                .is_some_and(|(role, _)| matches!(role, CreepRole::StaticMiner(id, _) if *id == source_id))
+        })
+        .filter(|creep| creep.ticks_to_live().is_none_or(|ticks| ticks > STATIC_MINER_REPLACE_TICKS))
+        .count()
+}
+
+// Short name for the role, said over the creep's head and used in the census
+fn role_label(role: &CreepRole) -> &'static str {
+    match role {
+        CreepRole::Builder => "Builder",
+        CreepRole::Worker => "Worker",
+        CreepRole::Miner => "Miner",
+        CreepRole::StaticMiner(..) => "Static",
+        CreepRole::Hauler => "Hauler",
+        CreepRole::Repairer => "Repairer",
+        CreepRole::Upgrader => "Upgrader",
+        CreepRole::Defender => "Defender",
+        CreepRole::RangedDefender => "Ranged",
+        CreepRole::Healer => "Healer",
+        CreepRole::Scout => "Scout",
+        CreepRole::MineralMiner => "Mineral",
+        CreepRole::DepositMiner => "Deposit",
+        CreepRole::PowerAttacker => "PowerAtk",
+        CreepRole::PowerHealer => "PowerHeal",
+        CreepRole::Claimer => "Claimer",
+        CreepRole::RemoteHarvester => "Remote",
+        CreepRole::RemoteHauler(..) => "RHauler",
+    }
+}
+
+// One line with the number of living creeps per role, and the energy in each owned room
+fn census_summary(creep_info: &CreepInfoMap) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for creep in game::creeps().values() {
+        let Some((role, _)) = creep_info.get(&creep.name()) else {
+            continue;
+        };
+        // static miners are one role whatever source they're bound to
+        let role_name = role_label(role);
+        match counts.iter_mut().find(|(r, _)| *r == role_name) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((role_name, 1)),
+        }
+    }
+
+    let roles = counts
+        .iter()
+        .map(|(role, count)| format!("{}={}", role, count))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let rooms = game::rooms()
+        .values()
+        .filter(|room| room.controller().is_some_and(|controller| controller.my()))
+        .map(|room| format!("{} energy {}/{}", room.name(), room.energy_available(), room.energy_capacity_available()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!("census: {} | {}", roles, rooms)
+}
+
+// How many creeps of each role the room wants, in priority order for breaking ties
+fn desired_census(room: &Room, find_cache: &mut FindCache) -> Vec<(CreepRole, usize)> {
+    // a static miner per source once the room can afford full size ones, each with a hauler;
+    // the tile is fixed when it's spawned, the container if there is one
+    let static_miners: Vec<CreepRole> = if room.energy_capacity_available() >= body_cost(&MINER_BODY) {
+        safe_sources(room, find_cache, find::SOURCES)
+            .into_iter()
+            .filter_map(|source| {
+                let mining_pos = drop_mining_spot(&source).or_else(|| mining_position(room, &source, find_cache, &[]))?;
+                Some(CreepRole::StaticMiner(source.id(), mining_pos))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let miners = static_miners.len();
+    // barriers are fortified by repairers too, decay or not
+    let repairers = if decayed_hits(room, find_cache) > REPAIRER_DECAYED_HITS
+        || weakest_barrier(room, find_cache).is_some()
+    {
+        REPAIRER_QUOTA
+    } else {
+        0
+    };
+    // a max level controller only takes so much a tick, which one upgrader already puts in
+    let upgraders = match room.controller() {
+        Some(controller) if controller.level() >= MAX_CONTROLLER_LEVEL => 1,
+        Some(_) => {
+            let banked = room
+                .storage()
+                .map_or(0, |storage| storage.store().get_used_capacity(Some(ResourceType::Energy)));
+            (1 + (banked.saturating_sub(STORAGE_ENERGY_FLOOR) / UPGRADER_STORAGE_STEP) as usize).min(UPGRADER_MAX)
+        }
+        None => 0,
+    };
+    // attackers that can only hit what's next to them are best kited from range
+    let hostiles = find_cache.find(room, find::HOSTILE_CREEPS);
+    let melee_only = hostiles.iter().any(|hostile| hostile.get_active_bodyparts(Part::Attack) > 0)
+        && hostiles.iter().all(|hostile| hostile.get_active_bodyparts(Part::RangedAttack) == 0);
+    let (defenders, ranged_defenders) = match (hostiles.is_empty(), melee_only) {
+        (true, _) => (0, 0),
+        (false, true) => (0, DEFENDER_QUOTA),
+        (false, false) => (DEFENDER_QUOTA, 0),
+    };
+    let healers = (defenders + ranged_defenders).div_ceil(DEFENDERS_PER_HEALER);
+
+    let mineral_miners = if harvestable_mineral(room, find_cache).is_some() { 1 } else { 0 };
+    // only rooms with a terminal to take the haul send anyone out; they count wherever they are
+    let deposit_miners = if room.terminal().is_some()
+        && ROOM_INTEL.with(|room_intel_refcell| intel::deposit_room(room.name(), &room_intel_refcell.borrow()).is_some())
+    {
+        1
+    } else {
+        0
+    };
+    // one per flagged room, spawned by whichever room can afford them; they count wherever they are
+    let claimers = if room.energy_capacity_available() >= body_cost(&CLAIMER_BODY) {
+        claim_flag_count()
+    } else {
+        0
+    };
+    // one per source in the reserved rooms next door, once there's storage to bring it home to;
+    // they count wherever they are
+    let remote_harvesters = if room.storage().is_some() { remote_sources(room.name()).len() } else { 0 };
+    // and a hauler for each of those sources with a container, bound to it wherever it is; the
+    // trip's measured here, so the body it's spawned with only has to look it up
+    let remote_haulers: Vec<CreepRole> = match room.storage() {
+        Some(storage) => remote_sources(room.name())
+            .into_iter()
+            .filter(|&(source_id, room_name)| {
+                let remote_room = game::rooms().get(room_name);
+                match (remote_room, source_id.resolve()) {
+                    (Some(remote_room), Some(source)) if source_container(&remote_room, find_cache, &source).is_some() => {
+                        measure_remote_path(&storage, &source);
+                        true
+                    }
+                    _ => false,
+                }
+            })
+            .map(|(source_id, room_name)| CreepRole::RemoteHauler(source_id, room_name))
+            .collect(),
+        None => Vec::new(),
+    };
+    // they count wherever they are, since they leave as soon as they're spawned
+    let scouts = if ROOM_INTEL.with(|room_intel_refcell| intel::needs_scouting(room.name(), &room_intel_refcell.borrow())) {
+        SCOUT_QUOTA
+    } else {
+        0
+    };
+
+    // a crew of one attacker and one healer per room that can reach a bank, if enabled at all;
+    // they count wherever they are
+    let power_crews = if game::flags().get(POWER_BANK_FLAG.to_string()).is_some()
+        && ROOM_INTEL.with(|room_intel_refcell| intel::power_bank_room(room.name(), &room_intel_refcell.borrow()).is_some())
+    {
+        1
+    } else {
+        0
+    };
+
+    let mut census = vec![
+        // defenders come first so they win any tie while the room is under attack
+        (CreepRole::Defender, defenders),
+        (CreepRole::RangedDefender, ranged_defenders),
+        (CreepRole::Healer, healers),
+        // workers first, so an empty room always starts by refilling its spawn
+        (CreepRole::Worker, WORKER_QUOTA),
+    ];
+    census.extend(static_miners.into_iter().map(|role| (role, 1)));
+    census.extend([
+        (CreepRole::Hauler, miners),
+        (CreepRole::Upgrader, upgraders),
+        (CreepRole::Builder, BUILDER_QUOTA),
+        (CreepRole::Repairer, repairers),
+        (CreepRole::MineralMiner, mineral_miners),
+        (CreepRole::DepositMiner, deposit_miners),
+        (CreepRole::PowerAttacker, power_crews),
+        (CreepRole::PowerHealer, power_crews),
+        (CreepRole::Scout, scouts),
+        (CreepRole::Claimer, claimers),
+        (CreepRole::RemoteHarvester, remote_harvesters),
+    ]);
+    census.extend(remote_haulers.into_iter().map(|role| (role, 1)));
+    census
+}
+
+// Lower goes first; emergencies always beat the economy
+fn spawn_priority(role: &CreepRole) -> u8 {
+    match role {
+        CreepRole::Defender | CreepRole::RangedDefender | CreepRole::Healer => 0,
+        _ => 1,
+    }
+}
+
+// Every role under its quota, counting creeps already spawned this tick, with the body it'd be
+// spawned with, most wanted first: by priority, then by the smallest share of the quota filled,
+// then by census order
+fn spawn_queue(
+    room: &Room,
+    creep_info: &CreepInfoMap,
+    find_cache: &mut FindCache,
+    capacity: u32,
+    pending: &[CreepRole],
+) -> Vec<(CreepRole, Vec<Part>)> {
+    let mut candidates: Vec<(u8, usize, usize, CreepRole)> = desired_census(room, find_cache)
+        .into_iter()
+        .filter_map(|(role, desired)| {
+            // claimers, deposit miners, power crews, remote harvesters and scouts spend their lives
+            // in other rooms
+            let current = if let CreepRole::StaticMiner(source_id, _) = role {
+                count_static_miners(creep_info, source_id)
+            } else if let CreepRole::RemoteHauler(source_id, _) = role {
+                count_remote_haulers(creep_info, source_id)
+            } else if matches!(
+                role,
+                CreepRole::Claimer
+                    | CreepRole::DepositMiner
+                    | CreepRole::PowerAttacker
+                    | CreepRole::PowerHealer
+                    | CreepRole::RemoteHarvester
+                    | CreepRole::Scout
+            ) {
+                count_role(creep_info, &role)
+            } else {
+                count_role_in_room(room, creep_info, &role)
+            };
+            // spawned by another spawn in the room this tick
+            let current = current + pending.iter().filter(|&pending_role| *pending_role == role).count();
+            (current < desired).then(|| (spawn_priority(&role), current, desired, role))
+        })
+        .collect();
+    // compare current / desired without dividing; the sort is stable so ties keep census order
+    candidates.sort_by(|(a_priority, a_current, a_desired, _), (b_priority, b_current, b_desired, _)| {
+        a_priority
+            .cmp(b_priority)
+            .then((a_current * b_desired).cmp(&(b_current * a_desired)))
+    });
+
+    candidates
+        .into_iter()
+        .map(|(_, _, _, role)| {
+            let body = build_body(capacity, &role);
+            (role, body)
+        })
+        .collect()
+}
+
+// Where to drop off carried energy: the closest spawn, extension or tower that needs
+// it, then storage if allowed, otherwise the controller or upkeep in its place. Towers
+// jump the queue while there are hostiles in the room.
+fn delivery_target(
+    creep: &Creep,
+    room: &Room,
+    find_cache: &mut FindCache,
+    claimed: &HashSet<RawObjectId>,
+    allow_storage: bool,
+) -> Option<CreepTarget> {
+    let under_attack = under_attack(room, find_cache);
+
+    if let Some(tower) = closest_low_tower(creep, room, find_cache).filter(|_| under_attack) {
+        Some(CreepTarget::FillTower(tower.id()))
+    } else if let Some(fill) = closest_fill_target(creep, room, find_cache, claimed) {
+        Some(fill)
+    } else if let Some(storage) = room.storage().filter(|storage| allow_storage && storage_accepts_deposit(storage)) {
+        Some(CreepTarget::DepositStorage(storage.id()))
+    } else {
+        upgrade_target(room, find_cache)
+    }
+}
+
+// The controller, unless it's at the top level and in no danger of downgrading; upgrading it
+// then only wastes energy, so it goes on repairs and fortifying instead
+fn upgrade_target(room: &Room, find_cache: &mut FindCache) -> Option<CreepTarget> {
+    let controller = room.controller()?;
+    if !controller_topped_up(&controller) {
+        return Some(CreepTarget::Upgrade(controller.id()));
+    }
+    if let Some(structure) = most_damaged_structure(room, find_cache) {
+        Some(CreepTarget::Repair(structure.as_structure().id()))
+    } else {
+        weakest_barrier(room, find_cache).map(|barrier| CreepTarget::Fortify(barrier.as_structure().id()))
+    }
+}
+
+// Whether the controller is at the top level with plenty of time left before it downgrades
+fn controller_topped_up(controller: &StructureController) -> bool {
+    controller.level() >= MAX_CONTROLLER_LEVEL
+        && controller.ticks_to_downgrade().is_some_and(|ticks| ticks >= MAX_LEVEL_DOWNGRADE_FLOOR)
+}
+
+// Whether the room's controller is close enough to downgrading that upgrading it comes before
+// everything else; once it is, that holds until the timer's back up to a safe level
+fn downgrade_emergency(room: &Room) -> bool {
+    let Some(controller) = room.controller().filter(|controller| controller.my()) else {
+        return false;
+    };
+    let ticks = controller.ticks_to_downgrade().unwrap_or(u32::MAX);
+    DOWNGRADE_EMERGENCIES.with(|emergencies_refcell| {
+        let mut emergencies = emergencies_refcell.borrow_mut();
+        if ticks < DOWNGRADE_DANGER_TICKS {
+            if emergencies.insert(room.name()) {
+                warn!("{} controller downgrades in {} ticks, everyone to upgrading", room.name(), ticks);
+            }
+        } else if ticks > DOWNGRADE_SAFE_TICKS && emergencies.remove(&room.name()) {
+            info!("{} controller is safe from downgrading again", room.name());
+        }
+        emergencies.contains(&room.name())
+    })
+}
+
+// Whether the room's controller is ours and missing our sign, with no creep on the way to fix it
+fn needs_sign(room: &Room, claimed: &HashSet<RawObjectId>) -> bool {
+    room.controller().is_some_and(|controller| {
+        controller.my()
+            && !claimed.contains(&controller.id().into())
+            && controller.sign().is_none_or(|sign| sign.text() != CONTROLLER_SIGN)
+    })
+}
+
+fn under_attack(room: &Room, find_cache: &mut FindCache) -> bool {
+    !find_cache.find(room, find::HOSTILE_CREEPS).is_empty()
+}
+
+// Under attack, builders and repairers keep the spawns fed first while they're running low,
+// so defenders can still be spawned
+fn emergency_fill_target(
+    creep: &Creep,
+    room: &Room,
+    find_cache: &mut FindCache,
+    claimed: &HashSet<RawObjectId>,
+) -> Option<CreepTarget> {
+    if !under_attack(room, find_cache) || !spawn_energy_low(room) {
+        return None;
+    }
+    closest_fill_target(creep, room, find_cache, claimed)
+}
+
+fn spawn_energy_low(room: &Room) -> bool {
+    (room.energy_available() as f32) < room.energy_capacity_available() as f32 * UPGRADE_THROTTLE_THRESHOLD
+}
+
+fn storage_accepts_deposit(storage: &StructureStorage) -> bool {
+    let store = storage.store();
+    store.get_free_capacity(Some(ResourceType::Energy)) > 0
+        && store.get_used_capacity(Some(ResourceType::Energy)) < STORAGE_DEPOSIT_CAP
+}
+
+fn tower_needs_energy(tower: &StructureTower) -> bool {
+    let store = tower.store();
+    (store.get_used_capacity(Some(ResourceType::Energy)) as f32)
+        < store.get_capacity(Some(ResourceType::Energy)) as f32 * TOWER_REFILL_THRESHOLD
+}
+
+// The closest spawn or extension with free capacity, or tower running low, preferring
+// ones no other creep is already filling
+fn closest_fill_target(
+    creep: &Creep,
+    room: &Room,
+    find_cache: &mut FindCache,
+    claimed: &HashSet<RawObjectId>,
+) -> Option<CreepTarget> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::MY_STRUCTURES)
+        .into_iter()
+        .filter_map(|structure| {
+            let fill = match &structure {
+                StructureObject::StructureSpawn(spawn)
+                    if spawn.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    CreepTarget::FillSpawn(spawn.id())
+                }
+                StructureObject::StructureExtension(extension)
+                    if extension.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    CreepTarget::FillExtension(extension.id())
+                }
+                StructureObject::StructureTower(tower) if tower_needs_energy(tower) => {
+                    CreepTarget::FillTower(tower.id())
+                }
+                _ => return None,
+            };
+            let taken = fill.claimed_id().is_some_and(|id| claimed.contains(&id));
+            Some(((taken, creep_pos.get_range_to(structure.pos())), fill))
+        })
+        .min_by_key(|(key, _)| *key)
+        .map(|(_, fill)| fill)
+}
+
+fn closest_low_tower(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<StructureTower> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::MY_STRUCTURES)
+        .into_iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureTower(tower) => Some(tower),
+            _ => None,
+        })
+        .filter(tower_needs_energy)
+        .min_by_key(|tower| creep_pos.get_range_to(tower.pos()))
+}
+
+// The hostile a ranged creep should shoot: healers first, then other ranged attackers,
+// then anything else, closest first within each
+fn ranged_attack_target(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<Creep> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::HOSTILE_CREEPS)
+        .into_iter()
+        .min_by_key(|hostile| {
+            let threat = if hostile.get_active_bodyparts(Part::Heal) > 0 {
+                0
+            } else if hostile.get_active_bodyparts(Part::RangedAttack) > 0 {
+                1
+            } else {
+                2
+            };
+            (threat, creep_pos.get_range_to(hostile.pos()))
+        })
+}
+
+// The friendly creep in the room with the lowest share of its hits left, if any are hurt
+fn most_damaged_creep(room: &Room, find_cache: &mut FindCache) -> Option<Creep> {
+    find_cache
+        .find(room, find::MY_CREEPS)
+        .into_iter()
+        .filter(|creep| creep.hits() < creep.hits_max())
+        .min_by(|a, b| hits_fraction(a.hits(), a.hits_max()).total_cmp(&hits_fraction(b.hits(), b.hits_max())))
+}
+
+// The step that best keeps the creep away from every melee hostile in the room: onto open
+// ground rather than a wall or another creep, never onto an exit tile where it'd bounce between
+// rooms, and out into the open rather than into a corner
+fn kite_step(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<Direction> {
+    let melee: Vec<Position> = find_cache
+        .find(room, find::HOSTILE_CREEPS)
+        .iter()
+        .filter(|hostile| hostile.get_active_bodyparts(Part::Attack) > 0)
+        .map(|hostile| hostile.pos())
+        .collect();
+    let blocked: Vec<Position> = find_cache
+        .find(room, find::CREEPS)
+        .iter()
+        .map(|other| other.pos())
+        .chain(
+            find_cache
+                .find(room, find::STRUCTURES)
+                .iter()
+                .filter(|structure| !matches!(structure.structure_type(), StructureType::Road | StructureType::Container | StructureType::Rampart))
+                .map(|structure| structure.pos()),
+        )
+        .collect();
+    let terrain = room.get_terrain();
+    let open = |pos: &Position| {
+        let (x, y) = pos.coords();
+        (1..ROOM_SIZE - 1).contains(&x) && (1..ROOM_SIZE - 1).contains(&y) && terrain.get(x, y) != Terrain::Wall && !blocked.contains(pos)
+    };
+
+    let here = creep.pos();
+    Direction::iter()
+        .filter_map(|&direction| {
+            let step = here.checked_add_direction(direction).ok()?;
+            if !open(&step) {
+                return None;
+            }
+            let distance = melee.iter().map(|hostile| step.get_range_to(*hostile)).min().unwrap_or(u32::MAX);
+            let room_to_move = Direction::iter()
+                .filter(|&&next| step.checked_add_direction(next).is_ok_and(|next| open(&next)))
+                .count();
+            Some(((distance, room_to_move), direction))
+        })
+        .max_by_key(|(key, _)| *key)
+        .map(|(_, direction)| direction)
+}
+
+// The hostile closest to any of the room's spawns
+fn hostile_nearest_spawn(room: &Room, find_cache: &mut FindCache) -> Option<Creep> {
+    let spawns = find_cache.find(room, find::MY_SPAWNS);
+    find_cache
+        .find(room, find::HOSTILE_CREEPS)
+        .into_iter()
+        .min_by_key(|hostile| spawns.iter().map(|spawn| spawn.pos().get_range_to(hostile.pos())).min().unwrap_or(u32::MAX))
+}
+
+// One of our ramparts next to the tile with no creep on it
+fn free_rampart_near(room: &Room, find_cache: &mut FindCache, pos: Position) -> Option<Position> {
+    let occupied: Vec<Position> = find_cache.find(room, find::CREEPS).iter().map(|creep| creep.pos()).collect();
+    find_cache
+        .find(room, find::MY_STRUCTURES)
+        .into_iter()
+        .filter(|structure| structure.structure_type() == StructureType::Rampart)
+        .map(|rampart| rampart.pos())
+        .find(|rampart_pos| rampart_pos.is_near_to(pos) && !occupied.contains(rampart_pos))
+}
+
+// A tile a few steps in from the room edge closest to the position
+fn edge_post(pos: Position) -> Position {
+    let (x, y) = pos.coords();
+    let far = ROOM_SIZE - 1 - DEFENDER_EDGE_INSET;
+    let (x, y) = [
+        (x, (DEFENDER_EDGE_INSET, y)),
+        (ROOM_SIZE - 1 - x, (far, y)),
+        (y, (x, DEFENDER_EDGE_INSET)),
+        (ROOM_SIZE - 1 - y, (x, far)),
+    ]
+    .into_iter()
+    .min_by_key(|(distance, _)| *distance)
+    .map(|(_, post)| post)
+    .unwrap_or((x, y));
+    let clamp = |coord: u8| RoomCoordinate::new(coord.clamp(DEFENDER_EDGE_INSET, far)).expect("clamped into the room");
+    Position::new(clamp(x), clamp(y), pos.room_name())
+}
+
+// The closest other friendly creep with working ATTACK parts
+fn closest_fighter(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<Creep> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::MY_CREEPS)
+        .into_iter()
+        .filter(|other| other.name() != creep.name() && is_fighter(other))
+        .min_by_key(|other| creep_pos.get_range_to(other.pos()))
+}
+
+// The other friendly fighter closest to any hostile in the room, the one about to take damage
+fn fighter_nearest_hostile(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<Creep> {
+    let hostiles = find_cache.find(room, find::HOSTILE_CREEPS);
+    find_cache
+        .find(room, find::MY_CREEPS)
+        .into_iter()
+        .filter(|other| other.name() != creep.name() && is_fighter(other))
+        .filter_map(|other| {
+            let range = hostiles.iter().map(|hostile| other.pos().get_range_to(hostile.pos())).min()?;
+            Some((range, other))
+        })
+        .min_by_key(|(range, _)| *range)
+        .map(|(_, other)| other)
+}
+
+// The friendly fighter with the smallest fraction of its hits left, if any of them are hurt
+fn most_damaged_fighter(room: &Room, find_cache: &mut FindCache) -> Option<Creep> {
+    find_cache
+        .find(room, find::MY_CREEPS)
+        .into_iter()
+        .filter(|creep| is_fighter(creep) && creep.hits() < creep.hits_max())
+        .min_by(|a, b| hits_fraction(a.hits(), a.hits_max()).total_cmp(&hits_fraction(b.hits(), b.hits_max())))
+}
+
+fn is_fighter(creep: &Creep) -> bool {
+    creep.get_active_bodyparts(Part::Attack) > 0 || creep.get_active_bodyparts(Part::RangedAttack) > 0
+}
+
+// Where to walk to when heading into a room
+fn room_center(room_name: RoomName) -> Position {
+    let center = RoomCoordinate::new(25).expect("25 is a valid room coordinate");
+    Position::new(center, center, room_name)
+}
+
+// Where idle creeps wait: the idle flag if it's in the room, otherwise a few steps from the
+// controller towards the middle of the room, out of the upgraders' way
+fn idle_spot(room: &Room) -> Option<Position> {
+    if let Some(flag) = game::flags().get(IDLE_FLAG.to_string()) {
+        if flag.pos().room_name() == room.name() {
+            return Some(flag.pos());
+        }
+    }
+
+    let center = room_center(room.name());
+    let mut pos = room.controller()?.pos();
+    for _ in 0..IDLE_CONTROLLER_OFFSET {
+        let Some(direction) = pos.get_direction_to(center) else {
+            break;
+        };
+        pos = pos.checked_add_direction(direction).ok()?;
+    }
+    Some(pos)
+}
+
+// The usual follow-up to an action: walk over if it's out of range, otherwise give up on the
+// target on any other error; a target someone else already filled up or emptied isn't worth a
+// warning
+fn handle_action<T>(
+    creep: &Creep,
+    target_pos: Position,
+    result: Result<T, ErrorCode>,
+    target: &mut Option<CreepTarget>,
+    action: &str,
+) {
+    match result {
+        Ok(_) => {}
+        Err(ErrorCode::NotInRange) => {
+            let _ = movement::move_to(creep, target_pos);
+        }
+        Err(ErrorCode::Full | ErrorCode::NotEnough) => *target = None,
+        Err(e) => {
+            warn!("couldn't {}: {:?}", action, e);
+            *target = None;
+        }
+    }
+}
+
+// Whether the creep is standing on a road or any other structure
+fn standing_on_structure(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> bool {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::STRUCTURES)
+        .iter()
+        .any(|structure| structure.pos() == creep_pos)
+}
+
+// The closest tile within range of the idle spot that's clear of walls, structures, sites and
+// other creeps
+fn free_idle_tile(creep: &Creep, room: &Room, find_cache: &mut FindCache, spot: Position) -> Option<Position> {
+    let mut taken: HashSet<Position> = find_cache
+        .find(room, find::STRUCTURES)
+        .iter()
+        .map(|structure| structure.pos())
+        .collect();
+    taken.extend(find_cache.find(room, find::CONSTRUCTION_SITES).iter().map(|site| site.pos()));
+    taken.extend(find_cache.find(room, find::CREEPS).iter().map(|other| other.pos()));
+
+    let range = IDLE_RANGE as i32;
+    let terrain = room.get_terrain();
+    let creep_pos = creep.pos();
+    (-range..=range)
+        .flat_map(|dx| (-range..=range).map(move |dy| (dx, dy)))
+        .filter_map(|offset| spot.checked_add(offset).ok())
+        .filter(|pos| {
+            let (x, y) = pos.coords();
+            terrain.get(x, y) != Terrain::Wall && !taken.contains(pos)
+        })
+        .min_by_key(|pos| creep_pos.get_range_to(*pos))
+}
+
+fn hits_fraction(hits: u32, hits_max: u32) -> f32 {
+    hits as f32 / hits_max.max(1) as f32
+}
+
+// The controller of the room with the claim flag in it, from sight or from scouting, or the
+// room itself if nothing's known about it yet
+fn claim_target() -> Option<CreepTarget> {
+    let flag = game::flags().get(CLAIM_FLAG.to_string())?;
+    let room_name = flag.pos().room_name();
+    if flag.room().and_then(|room| room.controller()).is_some_and(|controller| controller.my()) {
+        // already done, the flag can go
+        flag.remove();
+        return None;
+    }
+    if !can_claim_room() {
+        return None;
+    }
+    match known_controller(room_name) {
+        Some(controller_id) => Some(CreepTarget::Claim(controller_id, room_name)),
+        None => unseen_room(room_name),
+    }
+}
+
+// Whether the room isn't ours yet and, as far as we know, isn't anyone else's either; a claimer
+// can't take an owned controller on its own, so it's not worth spawning one for it
+fn claimable(room_name: RoomName) -> bool {
+    match game::rooms().get(room_name).and_then(|room| room.controller()) {
+        Some(controller) => controller.owner().is_none(),
+        None => ROOM_INTEL.with(|room_intel_refcell| {
+            room_intel_refcell
+                .borrow()
+                .get(&room_name)
+                .is_none_or(|room_intel| room_intel.owner.is_none())
+        }),
+    }
+}
+
+// Whether the controller is owned or reserved by another player
+fn held_by_others(creep: &Creep, controller: &StructureController) -> bool {
+    let me = creep.owner().username();
+    controller.owner().is_some_and(|owner| owner.username() != me)
+        || controller.reservation().is_some_and(|reservation| reservation.username() != me)
+}
+
+// Whether the GCL allows for another room on top of the ones we already own
+fn can_claim_room() -> bool {
+    let owned = game::rooms()
+        .values()
+        .filter(|room| room.controller().is_some_and(|controller| controller.my()))
+        .count();
+    (owned as u32) < game::gcl::level()
+}
+
+// The controller of a room flagged for reserving that no other creep is reserving yet, or the
+// room itself if nothing's known about it yet
+fn reserve_target(claimed: &HashSet<RawObjectId>) -> Option<CreepTarget> {
+    game::flags()
+        .values()
+        .filter(|flag| flag.name().starts_with(RESERVE_FLAG_PREFIX))
+        .find_map(|flag| {
+            let room_name = flag.pos().room_name();
+            let Some(controller_id) = known_controller(room_name) else {
+                return unseen_room(room_name);
+            };
+            (!claimed.contains(&controller_id.into())).then_some(CreepTarget::Reserve(controller_id, room_name))
+        })
+}
+
+// Flagged rooms still waiting for a claimer: the claim flag if the GCL allows for it, and every
+// reserve flag
+fn claim_flag_count() -> usize {
+    let flags = game::flags();
+    let claim = flags.get(CLAIM_FLAG.to_string()).is_some_and(|flag| claimable(flag.pos().room_name())) && can_claim_room();
+    let reserves = flags
+        .keys()
+        .filter(|name| name.starts_with(RESERVE_FLAG_PREFIX))
+        .count();
+    usize::from(claim) + reserves
+}
+
+// Sources in the rooms flagged for reserving next to the given one
+fn remote_sources(home: RoomName) -> Vec<(ObjectId<Source>, RoomName)> {
+    let mut rooms: Vec<RoomName> = game::flags()
+        .values()
+        .filter(|flag| flag.name().starts_with(RESERVE_FLAG_PREFIX))
+        .map(|flag| flag.pos().room_name())
+        .filter(|&room_name| game::map::get_room_linear_distance(home, room_name, false) == 1)
+        .filter(|&room_name| !ROOM_INTEL.with(|room_intel_refcell| intel::is_dangerous(room_name, &room_intel_refcell.borrow())))
+        .collect();
+    rooms.sort_by_key(|room_name| room_name.to_string());
+    rooms.dedup();
+    rooms
+        .into_iter()
+        .flat_map(|room_name| known_sources(room_name).into_iter().map(move |source_id| (source_id, room_name)))
+        .collect()
+}
+
+// The closest remote source to the creep no one else is harvesting, for any of our rooms with
+// storage to bring the energy home to
+fn remote_harvest_source(creep: &Creep, claimed: &HashSet<RawObjectId>) -> Option<(ObjectId<Source>, RoomName)> {
+    let here = creep.pos().room_name();
+    game::rooms()
+        .values()
+        .filter(|room| room.controller().is_some_and(|controller| controller.my()) && room.storage().is_some())
+        .flat_map(|room| remote_sources(room.name()))
+        .filter(|(source_id, _)| !claimed.contains(&(*source_id).into()))
+        .min_by_key(|&(_, room_name)| game::map::get_room_linear_distance(here, room_name, false))
+}
+
+// Steps from home storage to the remote source, as worked out when its hauler was first asked
+// for; remote rooms are always next door, so a room's width stands in until then
+fn remote_path_length(source_id: ObjectId<Source>) -> u32 {
+    REMOTE_PATHS.with(|remote_paths_refcell| remote_paths_refcell.borrow().get(&source_id).copied())
+        .unwrap_or(ROOM_SIZE as u32)
+}
+
+// Path from storage to the remote source, searched for only the first time it's asked for; a
+// search that gives up partway is kept as the straight-line guess, so it isn't retried every tick
+fn measure_remote_path(storage: &StructureStorage, source: &Source) {
+    let source_id = source.id();
+    if REMOTE_PATHS.with(|remote_paths_refcell| remote_paths_refcell.borrow().contains_key(&source_id)) {
+        return;
+    }
+    let result = pathfinder::search(storage.pos(), source.pos(), 1, Some(SearchOptions::default()));
+    let length = if result.incomplete() {
+        let rooms = game::map::get_room_linear_distance(storage.pos().room_name(), source.pos().room_name(), false);
+        ROOM_SIZE as u32 * rooms.max(1)
+    } else {
+        result.path().len() as u32
+    };
+    REMOTE_PATHS.with(|remote_paths_refcell| remote_paths_refcell.borrow_mut().insert(source_id, length));
+}
+
+// A room's sources, from sight if we have it or from scouting if we don't
+fn known_sources(room_name: RoomName) -> Vec<ObjectId<Source>> {
+    match game::rooms().get(room_name) {
+        Some(room) => room.find(find::SOURCES, None).iter().map(|source| source.id()).collect(),
+        None => ROOM_INTEL.with(|room_intel_refcell| {
+            room_intel_refcell
+                .borrow()
+                .get(&room_name)
+                .map(|intel| intel.source_ids.clone())
+                .unwrap_or_default()
+        }),
+    }
+}
+
+// Go and have a look at a room that's neither in sight nor scouted, since its objects can't be
+// known until then
+fn unseen_room(room_name: RoomName) -> Option<CreepTarget> {
+    let scouted = ROOM_INTEL.with(|room_intel_refcell| room_intel_refcell.borrow().contains_key(&room_name));
+    (game::rooms().get(room_name).is_none() && !scouted).then_some(CreepTarget::MoveToRoom(room_name))
+}
+
+// A room's controller, from sight if we have it or from scouting if we don't
+fn known_controller(room_name: RoomName) -> Option<ObjectId<StructureController>> {
+    match game::rooms().get(room_name) {
+        Some(room) => room.controller().map(|controller| controller.id()),
+        None => ROOM_INTEL.with(|room_intel_refcell| room_intel_refcell.borrow().get(&room_name)?.controller),
+    }
+}
+
+// The structure the dismantle flag is on, ours or not, so obsolete ones can be cleared out;
+// failing that the closest hostile structure in the flagged room
+fn dismantle_target(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<StructureObject> {
+    let flag = game::flags().get(DISMANTLE_FLAG.to_string())?;
+    let flag_pos = flag.pos();
+    if flag_pos.room_name() != room.name() {
+        return None;
+    }
+
+    // the rampart over whatever's flagged goes last
+    let flagged = find_cache
+        .find(room, find::STRUCTURES)
+        .into_iter()
+        .filter(|structure| structure.pos() == flag_pos && structure.as_dismantleable().is_some())
+        .min_by_key(|structure| structure.structure_type() == StructureType::Rampart);
+    if flagged.is_some() {
+        return flagged;
+    }
+
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::HOSTILE_STRUCTURES)
+        .into_iter()
+        .filter(|structure| structure.as_dismantleable().is_some())
+        .min_by_key(|structure| creep_pos.get_range_to(structure.pos()))
+}
+
+// The most valuable thing held by a hostile structure in the room flagged for looting, out of the
+// structures no other creep is emptying; the room needs to be in sight for them to be found
+fn plunder_target(claimed: &HashSet<RawObjectId>) -> Option<CreepTarget> {
+    let room = game::flags().get(LOOT_FLAG.to_string())?.room()?;
+    room.find(find::HOSTILE_STRUCTURES, None)
+        .into_iter()
+        .filter(|structure| !claimed.contains(&structure.as_structure().id().into()))
+        .filter_map(|structure| {
+            let store = structure.as_has_store()?.store();
+            let resource = store
+                .store_types()
+                .into_iter()
+                .max_by_key(|&resource| (loot_value(resource), store.get_used_capacity(Some(resource))))?;
+            Some((loot_value(resource), structure.as_structure().id(), resource))
+        })
+        .max_by_key(|(value, _, _)| *value)
+        .map(|(_, structure_id, resource)| CreepTarget::Plunder(structure_id, resource))
+}
+
+// Where the power bank crew goes: to the bank's room first, then for the attacker the bank itself;
+// once there the healer heals whoever the bank hits back like any other healer would
+fn power_bank_target(creep: &Creep, role: &CreepRole, room: &Room, find_cache: &mut FindCache) -> Option<CreepTarget> {
+    if !matches!(role, CreepRole::PowerAttacker | CreepRole::PowerHealer) {
+        return None;
+    }
+    let bank_room = ROOM_INTEL.with(|room_intel_refcell| intel::power_bank_room(room.name(), &room_intel_refcell.borrow()))?;
+    if bank_room != room.name() {
+        return Some(CreepTarget::MoveToRoom(bank_room));
+    }
+    // keep the intel current, so a cracked bank stops having crews spawned for it
+    let observed = RoomIntel::observe(room, find_cache);
+    ROOM_INTEL.with(|room_intel_refcell| room_intel_refcell.borrow_mut().insert(room.name(), observed));
+    if *role == CreepRole::PowerHealer {
+        return None;
+    }
+
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::STRUCTURES)
+        .into_iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructurePowerBank(bank) => Some(bank),
+            _ => None,
+        })
+        .min_by_key(|bank| creep_pos.get_range_to(bank.pos()))
+        .map(|bank| CreepTarget::AttackPowerBank(bank.id()))
+}
+
+// A power bank nearly cracked by one of our crews with nobody coming for the power yet, if the
+// hauler can carry all of it
+fn power_haul_target(creep: &Creep, claimed: &HashSet<RawObjectId>) -> Option<CreepTarget> {
+    game::flags().get(POWER_BANK_FLAG.to_string())?;
+    let bank_room = ROOM_INTEL.with(|room_intel_refcell| {
+        intel::power_bank_room(creep.pos().room_name(), &room_intel_refcell.borrow())
+    })?;
+    // only in sight while the crew's there
+    let room = game::rooms().get(bank_room)?;
+    let capacity = creep.store().get_capacity(None);
+    room.find(find::STRUCTURES, None).into_iter().find_map(|structure| match structure {
+        StructureObject::StructurePowerBank(bank)
+            if bank.hits() <= POWER_BANK_HAUL_HITS && bank.power() <= capacity && !claimed.contains(&bank.id().into()) =>
+        {
+            Some(CreepTarget::CollectPower(bank.id(), bank_room))
+        }
+        _ => None,
+    })
+}
+
+// Rough worth of a resource when there's more to loot than can be carried off: boosts and power
+// first, then other compounds and commodities, then raw minerals, then energy
+fn loot_value(resource: ResourceType) -> u8 {
+    match resource {
+        ResourceType::Energy => 0,
+        ResourceType::Power => 3,
+        _ if resource.boost().is_some() => 3,
+        _ if resource.reaction_components().is_some() || resource.commodity_recipe().is_some() => 2,
+        _ => 1,
+    }
+}
+
+// Back to our nearest room with storage to drop off the loot, unless there's nothing to drop off
+// or we're there already, in which case regular target picking takes it from here
+fn haul_home(creep: &Creep) -> Option<CreepTarget> {
+    if creep.store().get_used_capacity(None) == 0 {
+        return None;
+    }
+    home_room(creep).map(CreepTarget::MoveToRoom)
+}
+
+// The nearest owned room with storage, unless the creep's already in it
+fn home_room(creep: &Creep) -> Option<RoomName> {
+    let here = creep.pos().room_name();
+    let home = game::rooms()
+        .values()
+        .filter(|room| room.controller().is_some_and(|controller| controller.my()) && room.storage().is_some())
+        .map(|room| room.name())
+        .min_by_key(|&room_name| game::map::get_room_linear_distance(here, room_name, false))?;
+    (home != here).then_some(home)
+}
+
+// The room's mineral, if it has an extractor on it and isn't depleted
+fn harvestable_mineral(room: &Room, find_cache: &mut FindCache) -> Option<Mineral> {
+    let has_extractor = find_cache
+        .find(room, find::MY_STRUCTURES)
+        .iter()
+        .any(|structure| structure.structure_type() == StructureType::Extractor);
+    if !has_extractor {
+        return None;
+    }
+    find_cache
+        .find(room, find::MINERALS)
+        .into_iter()
+        .find(|mineral| mineral.mineral_amount() > 0)
+}
+
+// The room's controller, if it's short of safe modes and there's ghodium for another that the
+// creep can carry in one go
+fn safe_mode_target(creep: &Creep, room: &Room, claimed: &HashSet<RawObjectId>) -> Option<CreepTarget> {
+    let controller = room.controller().filter(|controller| {
+        controller.my() && controller.safe_mode_available() < SAFE_MODE_RESERVE && !claimed.contains(&controller.id().into())
+    })?;
+    if creep.store().get_capacity(None) < SAFE_MODE_COST {
+        return None;
+    }
+    ghodium_source(room, SAFE_MODE_COST)?;
+    Some(CreepTarget::GenerateSafeMode(controller.id()))
+}
+
+// Storage, then the terminal, whichever holds at least this much ghodium
+fn ghodium_source(room: &Room, amount: u32) -> Option<StructureObject> {
+    let storage = room
+        .storage()
+        .filter(|storage| storage.store().get_used_capacity(Some(ResourceType::Ghodium)) >= amount)
+        .map(StructureObject::from);
+    storage.or_else(|| {
+        room.terminal()
+            .filter(|terminal| terminal.store().get_used_capacity(Some(ResourceType::Ghodium)) >= amount)
+            .map(StructureObject::from)
+    })
+}
+
+// Compounds creeps of the role get boosted with before starting work, and the part each is for
+fn role_boosts(role: &CreepRole) -> &'static [(Part, ResourceType)] {
+    match role {
+        CreepRole::Defender => &[(Part::Attack, ResourceType::UtriumHydride)],
+        CreepRole::RangedDefender => &[(Part::RangedAttack, ResourceType::KeaniumOxide)],
+        CreepRole::Healer => &[(Part::Heal, ResourceType::LemergiumOxide)],
+        _ => &[],
+    }
+}
+
+// A lab holding enough of one of the role's boosts, and the energy, to boost every part of the
+// body it's for, out of the boosts the body doesn't have yet
+fn boost_lab(
+    room: &Room,
+    role: &CreepRole,
+    body: &[(Part, Option<ResourceType>)],
+    find_cache: &mut FindCache,
+) -> Option<StructureLab> {
+    role_boosts(role).iter().find_map(|&(part, compound)| {
+        let parts = body.iter().filter(|&&(p, boost)| p == part && boost.is_none()).count() as u32;
+        if parts == 0 {
+            return None;
+        }
+        find_cache
+            .find(room, find::MY_STRUCTURES)
+            .into_iter()
+            .find_map(|structure| match structure {
+                StructureObject::StructureLab(lab)
+                    if lab.store().get_used_capacity(Some(compound)) >= LAB_BOOST_MINERAL * parts
+                        && lab.store().get_used_capacity(Some(ResourceType::Energy)) >= LAB_BOOST_ENERGY * parts =>
+                {
+                    Some(lab)
+                }
+                _ => None,
+            })
+    })
+}
+
+// Labs boost whichever creeps are waiting next to them with whatever compound they hold
+fn boost_waiting_creeps(creep_info: &CreepInfoMap) {
+    let creeps = game::creeps();
+    for (creep_name, (_, target)) in creep_info {
+        let Some(CreepTarget::GetBoosted(lab_id)) = target else {
+            continue;
+        };
+        let (Some(creep), Some(lab)) = (creeps.get(creep_name.to_string()), lab_id.resolve()) else {
+            continue;
+        };
+        if creep.spawning() || !creep.pos().is_near_to(lab.pos()) {
+            continue;
+        }
+        if let Err(e) = lab.boost_creep(&creep, None) {
+            warn!("lab couldn't boost {}: {:?}", creep_name, e);
+        }
+    }
+}
+
+// Whether the room's extractor can be harvested through this tick
+fn extractor_ready(room: &Room, find_cache: &mut FindCache) -> bool {
+    find_cache.find(room, find::MY_STRUCTURES).iter().any(|structure| match structure {
+        StructureObject::StructureExtractor(extractor) => extractor.cooldown() == 0,
+        _ => false,
+    })
+}
+
+// The container next to the room's mineral, if there is one
+fn mineral_container(room: &Room, find_cache: &mut FindCache) -> Option<StructureContainer> {
+    let mineral_pos = find_cache.find(room, find::MINERALS).first()?.pos();
+    room_containers(room, find_cache).find(|container| container.pos().is_near_to(mineral_pos))
+}
+
+// A full load of minerals waiting in the mineral container, for a hauler to take to the terminal
+// or storage
+fn mineral_container_pickup(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<CreepTarget> {
+    mineral_deposit(room)?;
+    let container = mineral_container(room, find_cache)?;
+    let store = container.store();
+    let resource = store.store_types().into_iter().find(|&resource| {
+        resource != ResourceType::Energy && store.get_used_capacity(Some(resource)) >= creep.store().get_free_capacity(None) as u32
+    })?;
+    Some(CreepTarget::WithdrawResource(StructureObject::from(container).as_structure().id(), resource))
+}
+
+// Where mined minerals go: the terminal if there's room in it, otherwise storage
+fn mineral_deposit(room: &Room) -> Option<StructureObject> {
+    let terminal = room
+        .terminal()
+        .filter(|terminal| terminal.store().get_free_capacity(None) > 0)
+        .map(StructureObject::from);
+    terminal.or_else(|| {
+        room.storage()
+            .filter(|storage| storage.store().get_free_capacity(None) > 0)
+            .map(StructureObject::from)
+    })
+}
+
+// The labs set up in the room's memory and the reagent each one is kept stocked with
+fn lab_inputs(room: &Room) -> Vec<(ObjectId<StructureLab>, ResourceType)> {
+    let Ok(inputs) = Reflect::get(&room.memory(), &JsString::from(LAB_INPUTS_MEMORY_KEY)) else {
+        return Vec::new();
+    };
+    if !inputs.is_object() {
+        return Vec::new();
+    }
+    let inputs: Object = inputs.unchecked_into();
+
+    Object::entries(&inputs)
+        .iter()
+        .filter_map(|entry| {
+            let entry: Array = entry.unchecked_into();
+            let lab_id = entry.get(0).as_string()?.parse().ok()?;
+            let resource = ResourceType::from_js_value(&entry.get(1))?;
+            Some((lab_id, resource))
+        })
+        .collect()
+}
+
+// A lab short on `resource`: any lab with room for energy, or one set up for the reagent
+// that's running low on it
+fn lab_needing(room: &Room, find_cache: &mut FindCache, resource: ResourceType) -> Option<StructureLab> {
+    if resource == ResourceType::Energy {
+        return find_cache
+            .find(room, find::MY_STRUCTURES)
+            .into_iter()
+            .find_map(|structure| match structure {
+                StructureObject::StructureLab(lab) if lab.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => Some(lab),
+                _ => None,
+            });
+    }
+
+    lab_inputs(room)
+        .into_iter()
+        .filter(|(_, reagent)| *reagent == resource)
+        .filter_map(|(lab_id, _)| lab_id.resolve())
+        .find(|lab| lab.store().get_used_capacity(Some(resource)) < LAB_REAGENT_MIN)
+}
+
+// Where to fetch a reagent for the first lab running low on one, if the room has any of it
+fn lab_supply_target(room: &Room) -> Option<CreepTarget> {
+    lab_inputs(room).into_iter().find_map(|(lab_id, reagent)| {
+        let lab = lab_id.resolve()?;
+        if lab.store().get_used_capacity(Some(reagent)) >= LAB_REAGENT_MIN {
+            return None;
+        }
+        let source = resource_source(room, reagent)?;
+        Some(CreepTarget::WithdrawResource(source.as_structure().id(), reagent))
+    })
+}
+
+// Somewhere that's short on `resource` and is stocked by haulers: a lab, then the nuker, then
+// the power spawn
+fn stock_target(room: &Room, find_cache: &mut FindCache, resource: ResourceType) -> Option<CreepTarget> {
+    if let Some(lab) = lab_needing(room, find_cache, resource) {
+        Some(CreepTarget::FillLab(lab.id(), resource))
+    } else if let Some(nuker) = nuker_needing(room, find_cache, resource) {
+        Some(CreepTarget::FillNuker(nuker.id(), resource))
+    } else {
+        power_spawn_needing(room, find_cache, resource)
+            .map(|power_spawn| CreepTarget::FillPowerSpawn(power_spawn.id(), resource))
+    }
+}
+
+// The room's power spawn if it's running low on `resource` and the room can spare it
+fn power_spawn_needing(room: &Room, find_cache: &mut FindCache, resource: ResourceType) -> Option<StructurePowerSpawn> {
+    let power_spawn = find_cache
+        .find(room, find::MY_STRUCTURES)
+        .into_iter()
+        .find_map(|structure| match structure {
+            StructureObject::StructurePowerSpawn(power_spawn) => Some(power_spawn),
+            _ => None,
+        })?;
+    let store = power_spawn.store();
+    match resource {
+        ResourceType::Energy => (store.get_free_capacity(Some(ResourceType::Energy)) > 0
+            && room.storage().is_some_and(|storage| {
+                storage.store().get_used_capacity(Some(ResourceType::Energy)) > POWER_SPAWN_STORAGE_SURPLUS
+            }))
+        .then_some(power_spawn),
+        ResourceType::Power => (store.get_used_capacity(Some(ResourceType::Power)) < POWER_SPAWN_POWER_MIN).then_some(power_spawn),
+        _ => None,
+    }
+}
+
+// Where to fetch power for the power spawn, or energy for it when there's no power to get
+fn power_spawn_supply_target(room: &Room, find_cache: &mut FindCache) -> Option<CreepTarget> {
+    [ResourceType::Power, ResourceType::Energy].into_iter().find_map(|resource| {
+        power_spawn_needing(room, find_cache, resource)?;
+        let source = resource_source(room, resource)?;
+        Some(CreepTarget::WithdrawResource(source.as_structure().id(), resource))
+    })
+}
+
+// The room's nuker if it has room for `resource` and the room can spare it; energy only
+// comes out of a healthy storage surplus
+fn nuker_needing(room: &Room, find_cache: &mut FindCache, resource: ResourceType) -> Option<StructureNuker> {
+    let nuker = find_cache
+        .find(room, find::MY_STRUCTURES)
+        .into_iter()
+        .find_map(|structure| match structure {
+            StructureObject::StructureNuker(nuker) => Some(nuker),
+            _ => None,
+        })?;
+    if nuker.store().get_free_capacity(Some(resource)) <= 0 {
+        return None;
+    }
+    match resource {
+        ResourceType::Energy => room
+            .storage()
+            .is_some_and(|storage| storage.store().get_used_capacity(Some(ResourceType::Energy)) > NUKER_STORAGE_SURPLUS)
+            .then_some(nuker),
+        ResourceType::Ghodium => Some(nuker),
+        _ => None,
+    }
+}
+
+// Where to fetch energy or ghodium for the nuker, whichever of its stores is emptier
+fn nuker_supply_target(room: &Room, find_cache: &mut FindCache) -> Option<CreepTarget> {
+    [ResourceType::Energy, ResourceType::Ghodium]
+        .into_iter()
+        .filter_map(|resource| {
+            let nuker = nuker_needing(room, find_cache, resource)?;
+            // energy comes out of the storage surplus it was checked against, not the terminal
+            let source = match resource {
+                ResourceType::Energy => room.storage().map(StructureObject::from),
+                _ => resource_source(room, resource),
+            }?;
+            let store = nuker.store();
+            let fill = store.get_used_capacity(Some(resource)) as f32 / store.get_capacity(Some(resource)).max(1) as f32;
+            Some((fill, resource, source))
+        })
+        .min_by(|(a, ..), (b, ..)| a.total_cmp(b))
+        .map(|(_, resource, source)| CreepTarget::WithdrawResource(source.as_structure().id(), resource))
+}
+
+// A load of whatever storage holds more of than it keeps, sized to fit both the creep and the
+// terminal
+fn terminal_supply_target(creep: &Creep, room: &Room) -> Option<CreepTarget> {
+    let terminal = room.terminal()?;
+    let storage = room.storage()?;
+    let terminal_store = terminal.store();
+    let storage_store = storage.store();
+    let free = terminal_store.get_free_capacity(None).max(0) as u32;
+    let carry = creep.store().get_free_capacity(None).max(0) as u32;
+
+    storage_store.store_types().into_iter().find_map(|resource| {
+        let threshold = if resource == ResourceType::Energy {
+            if terminal_store.get_used_capacity(Some(resource)) >= TERMINAL_ENERGY_CAP {
+                return None;
+            }
+            TERMINAL_ENERGY_THRESHOLD
+        } else {
+            TERMINAL_MINERAL_THRESHOLD
+        };
+        let excess = storage_store.get_used_capacity(Some(resource)).saturating_sub(threshold);
+        let amount = excess.min(free).min(carry);
+        (amount > 0).then_some(CreepTarget::FillTerminal(terminal.id(), resource, amount))
+    })
+}
+
+// The terminal or storage holding some of `resource`, terminal first
+fn resource_source(room: &Room, resource: ResourceType) -> Option<StructureObject> {
+    let terminal = room
+        .terminal()
+        .filter(|terminal| terminal.store().get_used_capacity(Some(resource)) > 0)
+        .map(StructureObject::from);
+    terminal.or_else(|| {
+        room.storage()
+            .filter(|storage| storage.store().get_used_capacity(Some(resource)) > 0)
+            .map(StructureObject::from)
+    })
+}
+
+// The biggest dropped energy pile of at least `min_amount`, nearest first on ties
+fn largest_energy_pile(creep: &Creep, room: &Room, find_cache: &mut FindCache, min_amount: u32) -> Option<Resource> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::DROPPED_RESOURCES)
+        .into_iter()
+        .filter(|resource| resource.resource_type() == ResourceType::Energy && resource.amount() >= min_amount)
+        .max_by_key(|resource| (resource.amount(), Reverse(creep_pos.get_range_to(resource.pos()))))
+}
+
+// Haulers take everything out of tombstones and ruins as long as there's somewhere to put
+// what isn't energy; everyone else leaves all but the energy alone
+fn loots_any_resource(creep: &Creep, role: &CreepRole) -> bool {
+    *role == CreepRole::Hauler && creep.room().is_some_and(|room| mineral_deposit(&room).is_some())
+}
+
+// What to take out of a tombstone or ruin first: energy, then anything else if allowed
+fn loot_resource(store: &Store, any_resource: bool) -> Option<ResourceType> {
+    if store.get_used_capacity(Some(ResourceType::Energy)) > 0 {
+        Some(ResourceType::Energy)
+    } else if any_resource {
+        store.store_types().into_iter().next()
+    } else {
+        None
+    }
+}
+
+// The tombstone with something to loot that will decay soonest, nearest first on ties
+fn lootable_tombstone(creep: &Creep, room: &Room, find_cache: &mut FindCache, any_resource: bool) -> Option<Tombstone> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::TOMBSTONES)
+        .into_iter()
+        .filter(|tombstone| loot_resource(&tombstone.store(), any_resource).is_some())
+        .min_by_key(|tombstone| (tombstone.ticks_to_decay(), creep_pos.get_range_to(tombstone.pos())))
+}
+
+fn lootable_ruin(creep: &Creep, room: &Room, find_cache: &mut FindCache, any_resource: bool) -> Option<Ruin> {
+    let creep_pos = creep.pos();
+    find_cache
+        .find(room, find::RUINS)
+        .into_iter()
+        .filter(|ruin| loot_resource(&ruin.store(), any_resource).is_some())
+        .min_by_key(|ruin| creep_pos.get_range_to(ruin.pos()))
+}
+
+// Tombstones before they decay, then the closest dropped energy pile, then ruins, and
+// failing all that the closest container holding energy
+fn hauler_pickup_target(creep: &Creep, room: &Room, find_cache: &mut FindCache) -> Option<CreepTarget> {
+    let creep_pos = creep.pos();
+    let any_resource = loots_any_resource(creep, &CreepRole::Hauler);
+
+    if let Some(tombstone) = lootable_tombstone(creep, room, find_cache, any_resource) {
+        return Some(CreepTarget::LootTombstone(tombstone.id()));
+    }
+
+    // what's been dropped at the collection point is left for the other creeps
+    let collection_point = collection_point(room);
+    let pile = find_cache
+        .find(room, find::DROPPED_RESOURCES)
+        .into_iter()
+        .filter(|resource| resource.resource_type() == ResourceType::Energy)
+        .filter(|resource| !collection_point.is_some_and(|pos| resource.pos().in_range_to(pos, COLLECTION_POINT_RANGE)))
+        .min_by_key(|resource| creep_pos.get_range_to(resource.pos()));
+    if let Some(pile) = pile {
+        return Some(CreepTarget::Pickup(pile.id()));
+    }
+
+    if let Some(ruin) = lootable_ruin(creep, room, find_cache, any_resource) {
+        return Some(CreepTarget::LootRuin(ruin.id()));
+    }
+
+    // the controller container is filled by haulers, not emptied by them
+    let controller_container = controller_container(room, find_cache).map(|container| container.id());
+    room_containers(room, find_cache)
+        .filter(|container| {
+            Some(container.id()) != controller_container
+                && container.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+        })
+        .min_by_key(|container| creep_pos.get_range_to(container.pos()))
+        .map(|container| CreepTarget::WithdrawContainer(container.id()))
+}
+
+// The container within range of the room's controller; remembered between ticks, and only
+// looked for again once it's gone, or every so often while the room has none
+fn controller_container(room: &Room, find_cache: &mut FindCache) -> Option<StructureContainer> {
+    let now = game::time();
+    let cached = CONTROLLER_CONTAINERS.with(|containers_refcell| containers_refcell.borrow().get(&room.name()).copied());
+    match cached {
+        Some((Some(container_id), _)) => {
+            if let Some(container) = container_id.resolve() {
+                return Some(container);
+            }
+        }
+        Some((None, checked_at)) if now < checked_at + CONTROLLER_CONTAINER_RECHECK => return None,
+        _ => {}
+    }
+
+    let controller_pos = room.controller()?.pos();
+    let container = room_containers(room, find_cache)
+        .filter(|container| container.pos().in_range_to(controller_pos, CONTROLLER_CONTAINER_RANGE))
+        .min_by_key(|container| container.pos().get_range_to(controller_pos));
+    CONTROLLER_CONTAINERS.with(|containers_refcell| {
+        containers_refcell
+            .borrow_mut()
+            .insert(room.name(), (container.as_ref().map(|container| container.id()), now))
+    });
+    container
+}
+
+// The controller container, if it's running low and no other creep is on the way to fill it
+fn controller_container_needing(
+    room: &Room,
+    find_cache: &mut FindCache,
+    claimed: &HashSet<RawObjectId>,
+) -> Option<StructureContainer> {
+    controller_container(room, find_cache).filter(|container| {
+        let store = container.store();
+        !claimed.contains(&container.id().into())
+            && (store.get_used_capacity(Some(ResourceType::Energy)) as f32)
+                < store.get_capacity(Some(ResourceType::Energy)) as f32 * CONTROLLER_CONTAINER_REFILL_THRESHOLD
+    })
+}
+
+// Where full haulers with nowhere to deliver leave their energy: a couple of steps from the
+// first spawn towards the controller
+fn collection_point(room: &Room) -> Option<Position> {
+    let mut pos = room.find(find::MY_SPAWNS, None).into_iter().next()?.pos();
+    let controller_pos = room.controller()?.pos();
+    for _ in 0..COLLECTION_POINT_OFFSET {
+        let Some(direction) = pos.get_direction_to(controller_pos) else {
+            break;
+        };
+        pos = pos.checked_add_direction(direction).ok()?;
+    }
+    Some(pos)
+}
+
+// The container next to the source, if there is one
+fn source_container(room: &Room, find_cache: &mut FindCache, source: &Source) -> Option<StructureContainer> {
+    let source_pos = source.pos();
+    room_containers(room, find_cache).find(|container| container.pos().is_near_to(source_pos))
+}
+
+fn room_containers(room: &Room, find_cache: &mut FindCache) -> impl Iterator<Item = StructureContainer> {
+    find_cache
+        .find(room, find::STRUCTURES)
+        .into_iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureContainer(container) => Some(container),
+            _ => None,
+        })
+}
+
+// The closest container holding more than `min_energy` energy
+fn closest_energy_container(
+    creep: &Creep,
+    room: &Room,
+    find_cache: &mut FindCache,
+    min_energy: u32,
+) -> Option<StructureContainer> {
+    let creep_pos = creep.pos();
+    room_containers(room, find_cache)
+        .filter(|container| container.store().get_used_capacity(Some(ResourceType::Energy)) > min_energy)
+        .min_by_key(|container| creep_pos.get_range_to(container.pos()))
+}
+
+// How many living creeps are harvesting or mining at each source
+fn source_crowds(creep_info: &CreepInfoMap) -> SourceCrowds {
+    let creeps = game::creeps();
+    let mut crowds = SourceCrowds::new();
+    for (creep_name, (_, target)) in creep_info {
+        if let Some(CreepTarget::Harvest(source_id) | CreepTarget::Mine(source_id, _) | CreepTarget::DropMine(source_id)) = target {
+            if creeps.get(creep_name.to_string()).is_some() {
+                *crowds.entry(*source_id).or_default() += 1;
+            }
+        }
+    }
+    crowds
+}
+
+// The source with the fewest creeps at it that still has a tile free around it, closest first
+fn least_crowded_source(creep: &Creep, sources: Vec<Source>, source_crowds: &SourceCrowds) -> Option<Source> {
+    let creep_pos = creep.pos();
+    sources
+        .into_iter()
+        .filter_map(|source| {
+            let crowd = source_crowds.get(&source.id()).copied().unwrap_or(0);
+            (crowd < source_tiles(&source)).then(|| ((crowd, creep_pos.get_range_to(source.pos())), source))
+        })
+        .min_by_key(|(key, _)| *key)
+        .map(|(_, source)| source)
+}
+
+// The sources found with `ty` that no source keeper is guarding
+fn safe_sources<T>(room: &Room, find_cache: &mut FindCache, ty: T) -> Vec<Source>
+where
+    T: find::FindConstant<Item = Source>,
+{
+    find_cache
+        .find(room, ty)
+        .into_iter()
+        .filter(|source| !keeper_guarded(room, find_cache, source))
+        .collect()
+}
+
+// Whether a source keeper is guarding the source, or its lair is about to spawn one, with none of
+// our fighters in the room to deal with it
+fn keeper_guarded(room: &Room, find_cache: &mut FindCache, source: &Source) -> bool {
+    let source_pos = source.pos();
+    let keeper_near = find_cache.find(room, find::HOSTILE_CREEPS).iter().any(|hostile| {
+        hostile.owner().username() == SOURCE_KEEPER_USERNAME && hostile.pos().in_range_to(source_pos, KEEPER_GUARD_RANGE)
+    });
+    let lair_waking = find_cache.find(room, find::HOSTILE_STRUCTURES).iter().any(|structure| {
+        matches!(structure, StructureObject::StructureKeeperLair(lair)
+            if lair.pos().in_range_to(source_pos, KEEPER_GUARD_RANGE) && lair.ticks_to_spawn() <= KEEPER_SPAWN_WARNING_TICKS)
+    });
+    (keeper_near || lair_waking)
+        && !find_cache.find(room, find::MY_CREEPS).iter().any(is_fighter)
+}
+
+// How many creeps can stand next to the source at once
+fn source_tiles(source: &Source) -> usize {
+    let source_id = source.id();
+    if let Some(tiles) = SOURCE_TILES.with(|tiles_refcell| tiles_refcell.borrow().get(&source_id).copied()) {
+        return tiles;
+    }
+
+    let source_pos = source.pos();
+    let Some(terrain) = game::map::get_room_terrain(source_pos.room_name()) else {
+        return 0;
+    };
+    let tiles = (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+        .filter(|&offset| offset != (0, 0))
+        .filter_map(|offset| source_pos.checked_add(offset).ok())
+        .filter(|pos| {
+            let (x, y) = pos.coords();
+            terrain.get(x, y) != Terrain::Wall
+        })
+        .count();
+    SOURCE_TILES.with(|tiles_refcell| tiles_refcell.borrow_mut().insert(source_id, tiles));
+    tiles
+}
+
+// The source and tile each living miner is working
+fn taken_mining_spots(creep_info: &CreepInfoMap) -> Vec<(ObjectId<Source>, Position)> {
+    let creeps = game::creeps();
+    creep_info
+        .iter()
+        .filter(|(creep_name, _)| creeps.get(creep_name.to_string()).is_some())
+        .filter_map(|(_, (_, target))| match target {
+            Some(CreepTarget::Mine(source_id, pos)) => Some((*source_id, *pos)),
+            Some(CreepTarget::DropMine(source_id)) => Some((*source_id, drop_mining_spot(&source_id.resolve()?)?)),
+            _ => None,
+        })
+        .collect()
+}
+
+// Living creeps in the room holding energy by the spawn for its next refill
+fn energy_holders(creep_info: &CreepInfoMap, room_name: RoomName) -> usize {
+    let creeps = game::creeps();
+    creep_info
+        .iter()
+        .filter(|(_, (_, target))| matches!(target, Some(CreepTarget::HoldEnergy(_))))
+        .filter(|(creep_name, _)| {
+            creeps
+                .get(creep_name.to_string())
+                .is_some_and(|creep| creep.pos().room_name() == room_name)
+        })
+        .count()
+}
+
+// Sources a living drop miner has to itself
+fn drop_mined_sources(creep_info: &CreepInfoMap) -> Vec<ObjectId<Source>> {
+    let creeps = game::creeps();
+    creep_info
+        .iter()
+        .filter(|(creep_name, _)| creeps.get(creep_name.to_string()).is_some())
+        .filter_map(|(_, (_, target))| match target {
+            Some(CreepTarget::DropMine(source_id)) => Some(*source_id),
+            _ => None,
+        })
+        .collect()
+}
+
+// The tile a drop miner parks on: its source's container, or the site of one being built
+fn drop_mining_spot(source: &Source) -> Option<Position> {
+    let source_pos = source.pos();
+    let container = source_pos
+        .find_in_range(find::STRUCTURES, 1)
+        .into_iter()
+        .find(|structure| structure.structure_type() == StructureType::Container)
+        .map(|container| container.pos());
+    container.or_else(|| {
+        source_pos
+            .find_in_range(find::MY_CONSTRUCTION_SITES, 1)
+            .into_iter()
+            .find(|site| site.structure_type() == StructureType::Container)
+            .map(|site| site.pos())
+    })
+}
+
+// The source with the fewest miners that still has a free tile next to it, closest first, and
+// the tile to mine it from
+fn balanced_mining_spot(
+    creep: &Creep,
+    room: &Room,
+    find_cache: &mut FindCache,
+    taken_spots: &[(ObjectId<Source>, Position)],
+) -> Option<(Source, Position)> {
+    let creep_pos = creep.pos();
+    safe_sources(room, find_cache, find::SOURCES)
+        .into_iter()
+        .filter_map(|source| {
+            let taken: Vec<Position> = taken_spots
+                .iter()
+                .filter(|(source_id, _)| *source_id == source.id())
+                .map(|(_, pos)| *pos)
+                .collect();
+            let mining_pos = mining_position(room, &source, find_cache, &taken)?;
+            let key = (taken.len(), creep_pos.get_range_to(source.pos()));
+            Some((key, source, mining_pos))
+        })
+        .min_by_key(|(key, _, _)| *key)
+        .map(|(_, source, mining_pos)| (source, mining_pos))
+}
+
+// Living miners on their way to their mining tile that are too slow to get there on their own,
+// with the tile each is headed for
+fn stranded_miners(creep_info: &CreepInfoMap) -> Vec<(Creep, Position)> {
+    let creeps = game::creeps();
+    creep_info
+        .iter()
+        .filter_map(|(creep_name, (_, target))| {
+            let Some(CreepTarget::Mine(_, mining_pos)) = target else {
+                return None;
+            };
+            let miner = creeps.get(creep_name.to_string())?;
+            (!miner.spawning() && miner.pos() != *mining_pos && needs_pulling(&miner)).then_some((miner, *mining_pos))
+        })
+        .collect()
+}
+
+// Whether the creep has fewer working MOVE parts than other parts, so it's slowed down even on plains
+fn needs_pulling(creep: &Creep) -> bool {
+    (creep.get_active_bodyparts(Part::Move) as usize) * 2 < creep.body().len()
+}
+
+// Pick the tile a miner should park on for a source, preferring one holding a container, out of
+// the ones no other miner is on
+fn mining_position(room: &Room, source: &Source, find_cache: &mut FindCache, taken: &[Position]) -> Option<Position> {
+    let source_pos = source.pos();
+
+    let container = room_containers(room, find_cache)
+        .find(|container| container.pos().is_near_to(source_pos) && !taken.contains(&container.pos()));
+    if let Some(container) = container {
+        return Some(container.pos());
+    }
+
+    let terrain = room.get_terrain();
+    (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+        .filter(|&offset| offset != (0, 0))
+        .filter_map(|offset| source_pos.checked_add(offset).ok())
+        .find(|pos| {
+            let (x, y) = pos.coords();
+            terrain.get(x, y) != Terrain::Wall && !taken.contains(pos)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_body_fills_exactly_to_the_part_limit() {
+        // 25 [Carry, Move] units come to exactly 50 parts, so nothing is trimmed
+        let unit = [Part::Carry, Part::Move];
+        let body = scaled_body(&[], &unit, body_cost(&unit) * 25);
+        assert_eq!(body.len(), MAX_CREEP_SIZE as usize);
+        assert_eq!(body, unit.repeat(25));
+    }
+
+    #[test]
+    fn scaled_body_below_one_unit_still_gets_one() {
+        let fixed = [Part::Carry, Part::Move];
+        let unit = [Part::Work, Part::Work, Part::Move];
+        assert_eq!(scaled_body(&fixed, &unit, 0), [&fixed[..], &unit[..]].concat());
+        assert_eq!(scaled_body(&[], &unit, body_cost(&unit) - 1), unit.to_vec());
+    }
+
+    #[test]
+    fn scaled_body_overshoot_is_trimmed_by_whole_units() {
+        // 50 units of 3 parts would be 150; 16 whole units is the most that fits
+        let unit = [Part::Move, Part::Carry, Part::Work];
+        let body = scaled_body(&[], &unit, body_cost(&unit) * 50);
+        assert_eq!(body, unit.repeat(16));
+
+        // the fixed parts stay at the front and the units after them stay whole
+        let fixed = [Part::Carry, Part::Move];
+        let unit = [Part::Work, Part::Work, Part::Move];
+        let body = scaled_body(&fixed, &unit, 1_000_000);
+        assert_eq!(body.len(), MAX_CREEP_SIZE as usize);
+        assert_eq!(body, [&fixed[..], &unit.repeat(16)[..]].concat());
+    }
+
+    #[test]
+    fn fit_part_limit_keeps_the_last_unit() {
+        let fixed = [Part::Tough; 49];
+        let unit = [Part::Attack, Part::Move];
+        let body = [&fixed[..], &unit[..]].concat();
+        assert_eq!(fit_part_limit(body.clone(), fixed.len(), &unit), body);
+    }
+}
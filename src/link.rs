@@ -0,0 +1,82 @@
+use log::*;
+use screeps::{
+    constants::ResourceType,
+    enums::StructureObject,
+    find, game,
+    objects::{Room, StructureLink},
+    prelude::*,
+};
+
+// A link within this range of a source is fed by that source's miner
+const SOURCE_LINK_RANGE: u32 = 2;
+// The link closest to the controller receives energy, as long as it's within this range
+const CONTROLLER_LINK_RANGE: u32 = 4;
+// The link closest to storage is the hub haulers empty, as long as it's within this range
+const HUB_LINK_RANGE: u32 = 2;
+
+pub fn run_links() {
+    for room in game::rooms().values() {
+        let Some(controller) = room.controller().filter(|controller| controller.my()) else {
+            continue;
+        };
+
+        let links = my_links(&room);
+
+        let controller_pos = controller.pos();
+        let controller_link = links
+            .iter()
+            .filter(|link| link.pos().in_range_to(controller_pos, CONTROLLER_LINK_RANGE))
+            .min_by_key(|link| link.pos().get_range_to(controller_pos));
+        let hub = hub_link(&room);
+
+        // what each receiver can still take this tick; its store only updates once the tick's over,
+        // so sends already made this tick are taken off here
+        let mut receivers: Vec<(&StructureLink, u32)> = controller_link
+            .into_iter()
+            .chain(hub.as_ref())
+            .map(|receiver| (receiver, receiver.store().get_free_capacity(Some(ResourceType::Energy)).max(0) as u32))
+            .collect();
+
+        let sources = room.find(find::SOURCES, None);
+        for link in &links {
+            let next_to_source = sources
+                .iter()
+                .any(|source| link.pos().in_range_to(source.pos(), SOURCE_LINK_RANGE));
+            let is_receiver = controller_link.is_some_and(|receiver| receiver.id() == link.id())
+                || hub.as_ref().is_some_and(|hub| hub.id() == link.id());
+            // only send full loads, and only once the link is ready again
+            if is_receiver || !next_to_source || link.cooldown() > 0 || link.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
+                continue;
+            }
+            // the controller gets first pick, whatever it can't take goes to the hub for storage
+            let Some((receiver, free)) = receivers.iter_mut().find(|(_, free)| *free > 0) else {
+                break;
+            };
+
+            let amount = link.store().get_used_capacity(Some(ResourceType::Energy)).min(*free);
+            match link.transfer_energy(receiver, Some(amount)) {
+                Ok(()) => *free -= amount,
+                Err(e) => warn!("link couldn't transfer energy: {:?}", e),
+            }
+        }
+    }
+}
+
+// The link next to the room's storage, which haulers empty into it
+pub fn hub_link(room: &Room) -> Option<StructureLink> {
+    let storage_pos = room.storage()?.pos();
+    my_links(room)
+        .into_iter()
+        .filter(|link| link.pos().in_range_to(storage_pos, HUB_LINK_RANGE))
+        .min_by_key(|link| link.pos().get_range_to(storage_pos))
+}
+
+fn my_links(room: &Room) -> Vec<StructureLink> {
+    room.find(find::MY_STRUCTURES, None)
+        .into_iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureLink(link) => Some(link),
+            _ => None,
+        })
+        .collect()
+}
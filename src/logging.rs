@@ -1,13 +1,16 @@
 use std::{fmt::Write, panic};
 
-use js_sys::JsString;
+use js_sys::{JsString, Reflect};
 use log::*;
-use screeps::game;
+use screeps::{game, memory};
 use wasm_bindgen::prelude::wasm_bindgen;
 use web_sys::console;
 
 pub use log::LevelFilter::*;
 
+// Key in `Memory` that overrides the log level from the console, e.g. `Memory.logLevel = "debug"`
+const LOG_LEVEL_MEMORY_KEY: &str = "logLevel";
+
 struct JsLog;
 struct JsNotify;
 
@@ -31,8 +34,10 @@ impl log::Log for JsNotify {
 }
 
 pub fn setup_logging(verbosity: log::LevelFilter) {
+    // everything gets through fern; what's actually logged is down to the global max level, so it
+    // can be changed afterwards
     fern::Dispatch::new()
-        .level(verbosity)
+        .level(log::LevelFilter::Trace)
         .format(|out, message, record| {
             out.finish(format_args!(
                 "({}) {}: {}",
@@ -53,9 +58,29 @@ pub fn setup_logging(verbosity: log::LevelFilter) {
         )
         .apply()
         .expect("expected setup_logging to only ever be called once per instance");
+    log::set_max_level(verbosity);
     panic::set_hook(Box::new(panic_hook));
 }
 
+// Log at the level set in memory, or at `default` when there isn't one or it isn't a level
+pub fn apply_memory_level(default: log::LevelFilter) {
+    let level = Reflect::get(&memory::ROOT, &JsString::from(LOG_LEVEL_MEMORY_KEY))
+        .ok()
+        .and_then(|value| value.as_string())
+        .and_then(|value| value.parse::<log::LevelFilter>().ok())
+        .unwrap_or(default);
+    // confirmed at whichever of the two levels lets more through, so turning logging down to warn
+    // or error still says so
+    let previous = log::max_level();
+    if level > previous {
+        log::set_max_level(level);
+        warn!("log level set to {}", level);
+    } else if level < previous {
+        warn!("log level set to {}", level);
+        log::set_max_level(level);
+    }
+}
+
 #[wasm_bindgen]
 extern "C" {
     type Error;
@@ -70,7 +95,7 @@ extern "C" {
     fn stack_trace_limit(size: f32);
 }
 
-fn panic_hook(info: &panic::PanicInfo) {
+fn panic_hook(info: &panic::PanicHookInfo) {
     // import JS Error API to get backtrace info (backtraces don't work in wasm)
     // Node 8 does support this API: https://nodejs.org/docs/latest-v8.x/api/errors.html#errors_error_stack
 
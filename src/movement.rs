@@ -0,0 +1,31 @@
+use js_sys::{JsString, Object, Reflect};
+use log::*;
+use screeps::{constants::ErrorCode, objects::{Creep, MoveToOptions}, prelude::*};
+use wasm_bindgen::JsCast;
+
+// Ticks a creep keeps following a cached path before pathing again
+const REUSE_PATH_TICKS: u32 = 20;
+
+// Memory key move_to caches its serialized path under
+const MOVE_MEMORY_KEY: &str = "_move";
+
+// move_to, following the path cached in the creep's memory instead of pathing every tick; a
+// fatigued creep can't move anyway, so it doesn't get as far as pathing
+pub fn move_to<T: HasPosition>(creep: &Creep, target: T) -> Result<(), ErrorCode> {
+    if creep.fatigue() > 0 {
+        debug!("skipping move for {}, fatigue {}", creep.name(), creep.fatigue());
+        return Err(ErrorCode::Tired);
+    }
+    let options = MoveToOptions::new()
+        .reuse_path(REUSE_PATH_TICKS)
+        .serialize_memory(true);
+    creep.move_to_with_options(target, Some(options))
+}
+
+// Drop the cached path, so the next move_to paths from scratch
+pub fn forget_path(creep: &Creep) {
+    let memory = creep.memory();
+    if memory.is_object() {
+        let _ = Reflect::delete_property(memory.unchecked_ref::<Object>(), &JsString::from(MOVE_MEMORY_KEY));
+    }
+}
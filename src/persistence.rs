@@ -0,0 +1,55 @@
+use log::*;
+use screeps::raw_memory;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{intel::RoomIntelMap, CreepInfoMap};
+
+// RawMemory segment holding the serialized creep info; segments are kept separate from
+// `Memory` so that the game's own writes to it (like move_to path caching) can't clobber ours
+const CREEP_INFO_SEGMENT: u8 = 0;
+// Everything scouts have found out about other rooms
+const ROOM_INTEL_SEGMENT: u8 = 1;
+
+// Segments only become readable the tick after they're requested, so keep ours active
+pub fn request_segments() {
+    raw_memory::set_active_segments(&[CREEP_INFO_SEGMENT, ROOM_INTEL_SEGMENT]);
+}
+
+// Read creep info saved before the last global reset, if there is any
+pub fn load_creep_info() -> Option<CreepInfoMap> {
+    load(CREEP_INFO_SEGMENT, "creep info")
+}
+
+pub fn save_creep_info(creep_info: &CreepInfoMap) {
+    save(CREEP_INFO_SEGMENT, "creep info", creep_info)
+}
+
+pub fn load_room_intel() -> Option<RoomIntelMap> {
+    load(ROOM_INTEL_SEGMENT, "room intel")
+}
+
+pub fn save_room_intel(room_intel: &RoomIntelMap) {
+    save(ROOM_INTEL_SEGMENT, "room intel", room_intel)
+}
+
+fn load<T: DeserializeOwned>(segment: u8, what: &str) -> Option<T> {
+    let data = raw_memory::segments().get(segment)?;
+    if data.is_empty() {
+        return None;
+    }
+
+    match serde_json::from_str(&data) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("couldn't deserialize {}, starting fresh: {}", what, e);
+            None
+        }
+    }
+}
+
+fn save<T: Serialize>(segment: u8, what: &str, value: &T) {
+    match serde_json::to_string(value) {
+        Ok(data) => raw_memory::segments().set(segment, data),
+        Err(e) => warn!("couldn't serialize {}: {}", what, e),
+    }
+}
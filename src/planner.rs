@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use log::*;
+use screeps::{
+    constants::{StructureType, Terrain},
+    find, game,
+    local::Position,
+    objects::{FindPathOptions, Path, Room, RoomPosition},
+    pathfinder::SingleRoomCostResult,
+    prelude::*,
+};
+
+// Extensions go in rings around the first spawn out to this range
+const EXTENSION_PLAN_RADIUS: i32 = 6;
+// Road sites placed per room each time roads get planned, so a new room doesn't burn through
+// the global construction site limit at once
+const ROAD_SITES_PER_PLAN: usize = 20;
+
+// Place construction sites for any extensions the controller level allows but the room
+// doesn't have yet
+pub fn place_extensions() {
+    for room in game::rooms().values() {
+        let Some(controller) = room.controller().filter(|controller| controller.my()) else {
+            continue;
+        };
+
+        let allowed = StructureType::Extension.controller_structures(controller.level() as u32) as usize;
+        let built = room
+            .find(find::MY_STRUCTURES, None)
+            .iter()
+            .filter(|structure| structure.structure_type() == StructureType::Extension)
+            .count();
+        let planned = room
+            .find(find::MY_CONSTRUCTION_SITES, None)
+            .iter()
+            .filter(|site| site.structure_type() == StructureType::Extension)
+            .count();
+        let missing = allowed.saturating_sub(built + planned);
+        if missing == 0 {
+            continue;
+        }
+
+        for (x, y) in extension_spots(&room).into_iter().take(missing) {
+            if let Err(e) = room.create_construction_site(x, y, StructureType::Extension, None) {
+                warn!("couldn't place extension at {},{} in {}: {:?}", x, y, room.name(), e);
+            }
+        }
+    }
+}
+
+// Free tiles for extensions, nearest the spawn first, in a checkerboard so there's always a
+// way through between them
+fn extension_spots(room: &Room) -> Vec<(u8, u8)> {
+    let Some(spawn) = room.find(find::MY_SPAWNS, None).into_iter().next() else {
+        return Vec::new();
+    };
+    let (spawn_x, spawn_y) = spawn.pos().coords();
+
+    let mut occupied: HashSet<(u8, u8)> = room
+        .find(find::STRUCTURES, None)
+        .iter()
+        .map(|structure| structure.pos().coords())
+        .collect();
+    occupied.extend(room.find(find::CONSTRUCTION_SITES, None).iter().map(|site| site.pos().coords()));
+
+    // keep the tiles around sources and the controller clear for the creeps working them
+    let mut keep_clear: Vec<(u8, u8)> = room
+        .find(find::SOURCES, None)
+        .iter()
+        .map(|source| source.pos().coords())
+        .collect();
+    keep_clear.extend(room.controller().map(|controller| controller.pos().coords()));
+
+    let terrain = room.get_terrain();
+    let mut spots = Vec::new();
+    for radius in 2..=EXTENSION_PLAN_RADIUS {
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                if dx.abs().max(dy.abs()) != radius || (dx + dy) % 2 != 0 {
+                    continue;
+                }
+                let (x, y) = (spawn_x as i32 + dx, spawn_y as i32 + dy);
+                if !extension_in_bounds(x, y) {
+                    continue;
+                }
+                let (x, y) = (x as u8, y as u8);
+                let near_kept = keep_clear
+                    .iter()
+                    .any(|&(kx, ky)| kx.abs_diff(x) <= 1 && ky.abs_diff(y) <= 1);
+                if near_kept || occupied.contains(&(x, y)) || terrain.get(x, y) == Terrain::Wall {
+                    continue;
+                }
+                spots.push((x, y));
+            }
+        }
+    }
+    spots
+}
+
+// Nothing can be built on the room edges, and extensions leave the ring just inside them free
+// so they never wall off an exit
+fn extension_in_bounds(x: i32, y: i32) -> bool {
+    (2..=47).contains(&x) && (2..=47).contains(&y)
+}
+
+// Place road construction sites along the paths between the spawn, the sources and the
+// controller; rooms that still have road sites waiting get left alone until those are built
+pub fn place_roads() {
+    for room in game::rooms().values() {
+        let Some(controller) = room.controller().filter(|controller| controller.my()) else {
+            continue;
+        };
+        let Some(spawn) = room.find(find::MY_SPAWNS, None).into_iter().next() else {
+            continue;
+        };
+        let road_sites_pending = room
+            .find(find::MY_CONSTRUCTION_SITES, None)
+            .iter()
+            .any(|site| site.structure_type() == StructureType::Road);
+        if road_sites_pending {
+            continue;
+        }
+
+        let mut occupied: HashSet<(u8, u8)> = room
+            .find(find::STRUCTURES, None)
+            .iter()
+            .filter(|structure| structure.structure_type() != StructureType::Rampart)
+            .map(|structure| structure.pos().coords())
+            .collect();
+        occupied.extend(room.find(find::CONSTRUCTION_SITES, None).iter().map(|site| site.pos().coords()));
+
+        let spawn_pos = spawn.pos();
+        let controller_pos = controller.pos();
+        let mut routes: Vec<(Position, Position)> = room
+            .find(find::SOURCES, None)
+            .iter()
+            .flat_map(|source| [(spawn_pos, source.pos()), (source.pos(), controller_pos)])
+            .collect();
+        routes.push((spawn_pos, controller_pos));
+
+        let mut placed = 0;
+        'routes: for (from, to) in routes {
+            for (x, y) in road_tiles(&room, from, to) {
+                if placed >= ROAD_SITES_PER_PLAN {
+                    break 'routes;
+                }
+                if !occupied.insert((x, y)) {
+                    continue;
+                }
+                match room.create_construction_site(x, y, StructureType::Road, None) {
+                    Ok(()) => placed += 1,
+                    Err(e) => warn!("couldn't place road at {},{} in {}: {:?}", x, y, room.name(), e),
+                }
+            }
+        }
+    }
+}
+
+// Nothing can be built on the room edges; roads run right up to them so they lead out through
+// the exits
+fn road_in_bounds(x: u32, y: u32) -> bool {
+    (1..=48).contains(&x) && (1..=48).contains(&y)
+}
+
+// The tiles of the path from one point to next to another, ignoring creeps since they move
+// and treating swamp like plain since the road will pave over it anyway
+fn road_tiles(room: &Room, from: Position, to: Position) -> Vec<(u8, u8)> {
+    let options = FindPathOptions::<_, SingleRoomCostResult>::new()
+        .ignore_creeps(true)
+        .range(1)
+        .plain_cost(2)
+        .swamp_cost(2);
+    let steps = match room.find_path(&RoomPosition::from(from), &RoomPosition::from(to), Some(options)) {
+        Path::Vectorized(steps) => steps,
+        Path::Serialized(path) => Room::deserialize_path(&path),
+    };
+    steps
+        .into_iter()
+        .filter(|step| road_in_bounds(step.x, step.y))
+        .map(|step| (step.x as u8, step.y as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions_stay_off_the_edges_and_the_ring_inside_them() {
+        for edge in [0, 1, 48, 49] {
+            assert!(!extension_in_bounds(edge, 25));
+            assert!(!extension_in_bounds(25, edge));
+        }
+        assert!(extension_in_bounds(2, 2));
+        assert!(extension_in_bounds(47, 47));
+        assert!(!extension_in_bounds(-1, 25));
+    }
+
+    #[test]
+    fn roads_run_up_to_the_edges_but_not_onto_them() {
+        for edge in [0, 49] {
+            assert!(!road_in_bounds(edge, 25));
+            assert!(!road_in_bounds(25, edge));
+        }
+        assert!(road_in_bounds(1, 1));
+        assert!(road_in_bounds(48, 48));
+    }
+}
@@ -0,0 +1,29 @@
+use log::*;
+use screeps::{
+    constants::{ResourceType, POWER_SPAWN_ENERGY_RATIO},
+    enums::StructureObject,
+    game,
+    objects::StructurePowerSpawn,
+};
+
+pub fn run_power_spawns() {
+    for structure in game::structures().values() {
+        if let StructureObject::StructurePowerSpawn(power_spawn) = structure {
+            run_power_spawn(&power_spawn);
+        }
+    }
+}
+
+// Process power whenever there's enough of both power and energy for a round
+fn run_power_spawn(power_spawn: &StructurePowerSpawn) {
+    let store = power_spawn.store();
+    if store.get_used_capacity(Some(ResourceType::Power)) == 0
+        || store.get_used_capacity(Some(ResourceType::Energy)) < POWER_SPAWN_ENERGY_RATIO
+    {
+        return;
+    }
+
+    if let Err(e) = power_spawn.process_power() {
+        warn!("power spawn couldn't process power: {:?}", e);
+    }
+}
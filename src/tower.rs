@@ -0,0 +1,58 @@
+use log::*;
+use screeps::{enums::StructureObject, find, game, objects::StructureTower, prelude::*};
+
+use crate::{hits_fraction, repair_fraction};
+
+// Towers repair anything below this fraction of its repair goal
+const TOWER_REPAIR_THRESHOLD: f32 = 0.8;
+
+pub fn run_towers() {
+    for structure in game::structures().values() {
+        if let StructureObject::StructureTower(tower) = structure {
+            run_tower(&tower);
+        }
+    }
+}
+
+// Attack the closest hostile, otherwise heal the most damaged friendly creep,
+// otherwise repair the most damaged structure
+fn run_tower(tower: &StructureTower) {
+    let Some(room) = tower.room() else {
+        return;
+    };
+
+    if let Some(hostile) = tower.pos().find_closest_by_range(find::HOSTILE_CREEPS) {
+        if let Err(e) = tower.attack(&hostile) {
+            warn!("tower couldn't attack: {:?}", e);
+        }
+        return;
+    }
+
+    let patient = room
+        .find(find::MY_CREEPS, None)
+        .into_iter()
+        .filter(|creep| creep.hits() < creep.hits_max())
+        .min_by(|a, b| hits_fraction(a.hits(), a.hits_max()).total_cmp(&hits_fraction(b.hits(), b.hits_max())));
+    if let Some(patient) = patient {
+        if let Err(e) = tower.heal(&patient) {
+            warn!("tower couldn't heal: {:?}", e);
+        }
+        return;
+    }
+
+    let damaged = room
+        .find(find::STRUCTURES, None)
+        .into_iter()
+        .filter_map(|structure| {
+            let fraction = repair_fraction(&structure)?;
+            (fraction < TOWER_REPAIR_THRESHOLD).then_some((structure, fraction))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    if let Some((structure, _)) = damaged {
+        if let Some(repairable) = structure.as_repairable() {
+            if let Err(e) = tower.repair(repairable) {
+                warn!("tower couldn't repair: {:?}", e);
+            }
+        }
+    }
+}